@@ -0,0 +1,42 @@
+//! Checks that the third-party scripts we embed (see `src/util/assets.rs`) have been
+//! fetched, and that they match the hashes pinned in `res/static/SHA256SUMS`, so that a
+//! missing or tampered-with script fails the build with a useful message.
+
+use std::{fs, path::Path, process};
+
+use sha2::{Digest, Sha256};
+
+const STATIC_DIR: &str = "res/static";
+
+fn main() {
+    println!("cargo:rerun-if-changed={STATIC_DIR}");
+
+    if let Err(e) = check_assets(Path::new(STATIC_DIR)) {
+        eprintln!("error: {e}");
+        eprintln!("Run {STATIC_DIR}/fetch.sh to fetch the pinned scripts before building.");
+        process::exit(1);
+    }
+}
+
+fn check_assets(dir: &Path) -> Result<(), String> {
+    let sums = dir.join("SHA256SUMS");
+    let sums =
+        fs::read_to_string(&sums).map_err(|e| format!("Failed to read {}: {e}", sums.display()))?;
+
+    for line in sums.lines().filter(|line| !line.trim().is_empty()) {
+        let (expected, name) = line
+            .split_once("  ")
+            .ok_or_else(|| format!("Invalid line in SHA256SUMS: {line}"))?;
+        let path = dir.join(name);
+        let data = fs::read(&path).map_err(|e| format!("{} is missing: {e}", path.display()))?;
+        let actual = format!("{:x}", Sha256::digest(&data));
+        if actual != expected {
+            return Err(format!(
+                "{} doesn't match SHA256SUMS (expected {expected}, got {actual})",
+                path.display(),
+            ));
+        }
+    }
+
+    Ok(())
+}
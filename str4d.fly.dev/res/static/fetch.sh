@@ -0,0 +1,60 @@
+#!/bin/sh
+# Fetches the pinned third-party scripts that we serve from `/static/`.
+#
+# These are embedded into the binary (see `src/util/assets.rs`), so this needs to be run
+# before building. Every script is checked against its SHA-256 hash in `SHA256SUMS`, and
+# nothing is replaced unless all of them match.
+#
+# To upgrade a script, change its version here and run `./fetch.sh --pin`, which records
+# the hashes of whatever was downloaded in `SHA256SUMS`. Check the new hashes against the
+# upstream release before committing them.
+set -eu
+cd "$(dirname "$0")"
+
+pin=false
+if [ "${1:-}" = "--pin" ]; then
+    pin=true
+fi
+
+tmp="$(mktemp -d)"
+trap 'rm -rf "$tmp"' EXIT
+
+fetch() {
+    wget -q -O "$tmp/$1" "$2"
+}
+
+fetch chart.umd.js \
+    https://cdn.jsdelivr.net/npm/chart.js@4.4.7/dist/chart.umd.js
+fetch luxon.min.js \
+    https://cdn.jsdelivr.net/npm/luxon@3.5.0/build/global/luxon.min.js
+fetch chartjs-adapter-luxon.umd.min.js \
+    https://cdn.jsdelivr.net/npm/chartjs-adapter-luxon@1.3.1/dist/chartjs-adapter-luxon.umd.min.js
+fetch sigma.min.js \
+    https://cdnjs.cloudflare.com/ajax/libs/sigma.js/2.4.0/sigma.min.js
+fetch graphology.umd.min.js \
+    https://cdnjs.cloudflare.com/ajax/libs/graphology/0.26.0/graphology.umd.min.js
+fetch graphology-library.min.js \
+    https://github.com/graphology/graphology/releases/download/0.26.0/graphology-library.min.js
+
+if $pin; then
+    (cd "$tmp" && sha256sum -- *.js) >SHA256SUMS
+    echo "Pinned new hashes in SHA256SUMS; check them before committing."
+else
+    if [ ! -f SHA256SUMS ]; then
+        echo "SHA256SUMS is missing; run ./fetch.sh --pin to create it." >&2
+        exit 1
+    fi
+    for script in "$tmp"/*.js; do
+        name="$(basename "$script")"
+        if ! grep -q "  $name\$" SHA256SUMS; then
+            echo "No pinned hash for $name in SHA256SUMS." >&2
+            exit 1
+        fi
+    done
+    if ! (cd "$tmp" && sha256sum --quiet --strict -c -) <SHA256SUMS; then
+        echo "Fetched scripts don't match SHA256SUMS; refusing to use them." >&2
+        exit 1
+    fi
+fi
+
+mv "$tmp"/*.js .
@@ -1,30 +1,75 @@
+use std::sync::{Arc, LazyLock};
 use std::time::Duration;
 
 use askama::Template;
 use askama_web::WebTemplate;
-use axum::{Extension, Json, Router, routing::get};
-use cached::proc_macro::cached;
+use axum::{Extension, Router, routing::get};
+
+use crate::{
+    config::Config,
+    util::{
+        cache::{AgedJson, Cache, Warming},
+        error::{ErrorPages, Problem},
+        http_cache::CachePolicy,
+        scheduler::Job,
+        supervisor::{ServiceState, Supervisor},
+        upstream,
+    },
+};
 
 mod github;
 pub(crate) mod network;
 
+static NETWORK_MAP: LazyLock<Cache<network::Map, Arc<network::Error>>> =
+    LazyLock::new(|| Cache::new("atp.fyi-network-map", 1, Duration::from_secs(600)));
+
+static ROADMAP: LazyLock<Cache<github::Roadmap, Arc<github::Error>>> =
+    LazyLock::new(|| Cache::new("atp.fyi-roadmap", 1, Duration::from_secs(60)));
+
 pub(crate) fn build() -> Router {
-    Router::new()
+    let router = Router::new()
         .route("/", get(index))
         .route("/network", get(network))
-        .route("/roadmap", get(roadmap))
-        .route("/api/network-map", get(network_map))
+        .route(
+            "/roadmap",
+            get(roadmap).layer(CachePolicy::public(ROADMAP.ttl())),
+        )
+        .route(
+            "/api/network-map",
+            get(network_map).layer(CachePolicy::public(NETWORK_MAP.ttl())),
+        );
+    ErrorPages::new(|error| ErrorPage { error }).apply(router)
+}
+
+#[derive(Template)]
+#[template(path = "atp.fyi/error.html")]
+struct ErrorPage {
+    error: Problem,
 }
 
 #[derive(Clone, Template, WebTemplate)]
 #[template(path = "atp.fyi/index.html")]
 struct Index {
     rates: Option<(network::firehose::FirehoseRate, Duration)>,
+    /// Explains why `rates` is missing, if the monitor is failing.
+    monitor_error: Option<String>,
 }
 
-async fn index() -> Index {
+async fn index(Extension(supervisor): Extension<Supervisor>) -> Index {
+    let monitor_error =
+        supervisor
+            .status(network::firehose::SERVICE)
+            .and_then(|status| match status.state {
+                ServiceState::Backoff { retry_at } => Some(format!(
+                    "The firehose monitor is unavailable (retrying at {}).",
+                    retry_at.format("%H:%M:%S UTC"),
+                )),
+                _ => None,
+            });
+
     Index {
         rates: network::firehose::average_rates_per_min().await,
+        monitor_error,
     }
 }
 
@@ -36,35 +81,122 @@ async fn network() -> Network {
     Network {}
 }
 
-#[cached(time = 600, key = "()", convert = r##"{}"##)]
-async fn network_map(Extension(client): Extension<reqwest::Client>) -> Json<Option<network::Map>> {
-    let map = match self::network::render_map(&client).await {
-        Ok(map) => Some(map),
-        Err(e) => {
-            tracing::error!("Failed to render network map: {:?}", e);
-            None
-        }
-    };
+async fn network_map(
+    Extension(client): Extension<upstream::Client>,
+    Extension(config): Extension<Arc<Config>>,
+    Extension(supervisor): Extension<Supervisor>,
+) -> AgedJson<network::Map> {
+    let (dir, key) = (config.cache.dir.clone(), network_map_key(&config));
+    let map = NETWORK_MAP
+        .read(&supervisor.clone(), dir, key, move || {
+            fetch_network_map(client, config, supervisor)
+        })
+        .await;
 
-    Json(map)
+    AgedJson(map)
+}
+
+fn network_map_key(config: &Config) -> String {
+    let upstreams = &config.upstreams;
+    format!(
+        "{}|{}|{}|{}",
+        upstreams.bluesky_pds, upstreams.feeds_mod, upstreams.relay, config.bluesky.handle,
+    )
+}
+
+async fn fetch_network_map(
+    client: upstream::Client,
+    config: Arc<Config>,
+    supervisor: Supervisor,
+) -> Result<network::Map, Arc<network::Error>> {
+    let res = self::network::render_map(&client, &config).await;
+    supervisor
+        .data_sources()
+        .record("atp.fyi network map", &res);
+    res.map_err(Arc::new)
+}
+
+/// Keeps the network map warm.
+pub(crate) fn warm_network_map(
+    client: &upstream::Client,
+    config: &Arc<Config>,
+    supervisor: &Supervisor,
+) -> Job {
+    let (client, config, supervisor) = (client.clone(), config.clone(), supervisor.clone());
+    Job::new(
+        "warm atp.fyi network map",
+        Duration::from_secs(600),
+        move |_| {
+            let (client, config, supervisor) = (client.clone(), config.clone(), supervisor.clone());
+            async move {
+                let (dir, key) = (config.cache.dir.clone(), network_map_key(&config));
+                NETWORK_MAP
+                    .warm(dir, key, || fetch_network_map(client, config, supervisor))
+                    .await
+                    .map_err(|e| anyhow::anyhow!("{e}"))?;
+                Ok(())
+            }
+        },
+    )
+    .jitter(Duration::from_secs(60))
 }
 
 #[derive(Clone, Template, WebTemplate)]
 #[template(path = "atp.fyi/roadmap.html")]
 struct Roadmap {
-    roadmap: Option<github::Roadmap>,
+    roadmap: github::Roadmap,
+}
+
+async fn roadmap(
+    Extension(client): Extension<upstream::Client>,
+    Extension(config): Extension<Arc<Config>>,
+    Extension(supervisor): Extension<Supervisor>,
+) -> Result<Roadmap, Warming> {
+    let (dir, key) = (config.cache.dir.clone(), roadmap_key(&config));
+    let roadmap = ROADMAP
+        .read(&supervisor.clone(), dir, key, move || {
+            fetch_roadmap(client, config, supervisor)
+        })
+        .await
+        .ok_or(Warming)?;
+    Ok(Roadmap {
+        roadmap: roadmap.value,
+    })
+}
+
+fn roadmap_key(config: &Config) -> String {
+    config.upstreams.github_graphql.clone()
+}
+
+async fn fetch_roadmap(
+    client: upstream::Client,
+    config: Arc<Config>,
+    supervisor: Supervisor,
+) -> Result<github::Roadmap, Arc<github::Error>> {
+    let res = self::github::get_roadmap(&client, &config).await;
+    supervisor.data_sources().record("atp.fyi roadmap", &res);
+    res.map_err(Arc::new)
 }
 
-#[cached(time = 60)]
-async fn roadmap() -> Roadmap {
-    let roadmap = match self::github::get_roadmap().await {
-        Ok(roadmap) => Some(roadmap),
-        Err(e) => {
-            tracing::error!("Failed to get roadmap: {:?}", e);
-            None
+/// Keeps the roadmap warm.
+pub(crate) fn warm_roadmap(
+    client: &upstream::Client,
+    config: &Arc<Config>,
+    supervisor: &Supervisor,
+) -> Job {
+    let (client, config, supervisor) = (client.clone(), config.clone(), supervisor.clone());
+    Job::new("warm atp.fyi roadmap", ROADMAP.ttl(), move |_| {
+        let (client, config, supervisor) = (client.clone(), config.clone(), supervisor.clone());
+        async move {
+            let (dir, key) = (config.cache.dir.clone(), roadmap_key(&config));
+            ROADMAP
+                .warm(dir, key, || fetch_roadmap(client, config, supervisor))
+                .await
+                .map_err(|e| anyhow::anyhow!("{e}"))?;
+            Ok(())
         }
-    };
-    Roadmap { roadmap }
+    })
+    .jitter(Duration::from_secs(10))
 }
 
 mod filters {
@@ -2,8 +2,12 @@ use std::env;
 use std::fmt;
 
 use graphql_client::GraphQLQuery;
+use serde::{Deserialize, Serialize};
 
-use crate::util::github;
+use crate::{
+    config::Config,
+    util::{github, upstream},
+};
 
 use self::social_app_query::SocialAppQueryRepositoryIssuesEdgesNodeLabelsEdges;
 
@@ -47,8 +51,11 @@ impl github::PaginatedQuery for SocialAppQuery {
     }
 }
 
-pub(super) async fn get_roadmap() -> Result<Roadmap, Error> {
-    let client = github::Client::new("atp.fyi")?;
+pub(super) async fn get_roadmap(
+    client: &upstream::Client,
+    config: &Config,
+) -> Result<Roadmap, Error> {
+    let client = github::Client::new(client, "atp.fyi", config)?;
 
     let data = client
         .post_paginated_graphql::<SocialAppQuery>(social_app_query::Variables { after: None })
@@ -65,8 +72,7 @@ pub(super) async fn get_roadmap() -> Result<Roadmap, Error> {
         .edges
         .into_iter()
         .flat_map(|issues| issues.into_iter())
-        .map(|e| e.and_then(|edge| edge.node))
-        .flatten()
+        .filter_map(|e| e.and_then(|edge| edge.node))
         .filter_map(|issue| {
             issue
                 .labels
@@ -128,7 +134,7 @@ impl Author {
     }
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub(super) struct Roadmap {
     pub(super) discussing: Issues,
     pub(super) planned: Issues,
@@ -136,7 +142,7 @@ pub(super) struct Roadmap {
     pub(super) putting_off: Issues,
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub(super) struct Issues {
     pub(super) devs: Vec<Issue>,
     pub(super) community: Vec<Issue>,
@@ -151,7 +157,7 @@ impl Issues {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub(super) struct Issue {
     number: i64,
     title: String,
@@ -1,10 +1,12 @@
-use std::collections::BTreeMap;
+use std::{borrow::Cow, collections::BTreeMap, fmt};
 
 use atrium_api::{
     app::bsky::{feed, labeler},
     xrpc,
 };
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, util::upstream};
 
 pub(crate) mod firehose;
 mod services;
@@ -15,8 +17,8 @@ const NODE_MAX_AREA: f64 = 225.0;
 const EDGE_MIN_SIZE: f64 = 1.0;
 const EDGE_MAX_SIZE: f64 = 10.0;
 
-pub(super) async fn render_map(client: &reqwest::Client) -> Result<Map, Error> {
-    let network = services::enumerate(client).await?;
+pub(super) async fn render_map(client: &upstream::Client, config: &Config) -> Result<Map, Error> {
+    let network = services::enumerate(client, config).await?;
     let rates = firehose::average_rates_per_min()
         .await
         .map(|(rates, _)| rates)
@@ -348,11 +350,11 @@ impl NodeBuilder {
         // - All PDS users contribute towards all events being emitted from the relay.
         // - All labels reach all AppViews.
         // - All users who have authored posts contribute to AppViews.
-        let total_pds_users: usize = network.pdss.iter().map(|(_, pds)| pds.account_count).sum();
+        let total_pds_users: usize = network.pdss.values().map(|pds| pds.account_count).sum();
         let largest_pds_users: usize = network
             .pdss
-            .iter()
-            .map(|(_, pds)| pds.account_count)
+            .values()
+            .map(|pds| pds.account_count)
             .max()
             .unwrap_or(total_pds_users);
         let max_relay_rate = rates.ops_total;
@@ -380,7 +382,7 @@ impl NodeBuilder {
             relay_scale: NodeScale::new(0.01, 0.01, max_relay_rate),
             labeler_scale: NodeScale::new(1.0, 1.0, max_labeler_likes as f64),
             feed_scale: NodeScale::new(1.0, min_feed_likes as f64, max_feed_likes as f64),
-            app_view_scale: NodeScale::new(1.0, 1.0, max_relay_rate as f64),
+            app_view_scale: NodeScale::new(1.0, 1.0, max_relay_rate),
             total_pds_accounts: total_pds_users,
         }
     }
@@ -489,7 +491,7 @@ impl EdgeBuilder {
             from,
             to,
             size: EDGE_MIN_SIZE,
-            colour: "#ababff",
+            colour: "#ababff".into(),
         }
     }
 
@@ -498,7 +500,7 @@ impl EdgeBuilder {
             from,
             to,
             size: EDGE_MIN_SIZE.max(ops * self.relay_scale),
-            colour: "#ffd17a",
+            colour: "#ffd17a".into(),
         }
     }
 
@@ -507,7 +509,7 @@ impl EdgeBuilder {
             from,
             to,
             size: EDGE_MIN_SIZE.max(ops * self.relay_scale),
-            colour: "#ffd17a",
+            colour: "#ffd17a".into(),
         }
     }
 
@@ -516,7 +518,7 @@ impl EdgeBuilder {
             from,
             to,
             size: EDGE_MIN_SIZE,
-            colour: "#ff8f8f",
+            colour: "#ff8f8f".into(),
         }
     }
 
@@ -525,18 +527,18 @@ impl EdgeBuilder {
             from,
             to,
             size: EDGE_MIN_SIZE.max(ops * self.relay_scale),
-            colour: "#ffd17a",
+            colour: "#ffd17a".into(),
         }
     }
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub(super) struct Map {
     nodes: Vec<Node>,
     edges: Vec<Edge>,
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Deserialize, Serialize)]
 struct Node {
     group: Group,
     subgroup: String,
@@ -546,7 +548,7 @@ struct Node {
     bsky_operated: bool,
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Deserialize, Serialize)]
 enum Group {
     Pds,
     Relay,
@@ -555,16 +557,15 @@ enum Group {
     AppView,
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Deserialize, Serialize)]
 struct Edge {
     from: usize,
     to: usize,
     size: f64,
-    colour: &'static str,
+    colour: Cow<'static, str>,
 }
 
 #[derive(Debug)]
-#[allow(dead_code)]
 pub(super) enum Error {
     BlueskyAuthRequired,
     Feed(xrpc::Error<feed::get_suggested_feeds::Error>),
@@ -572,6 +573,20 @@ pub(super) enum Error {
     Labeler(xrpc::Error<labeler::get_services::Error>),
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::BlueskyAuthRequired => write!(
+                f,
+                "Failed to sign in to Bluesky (is BLUESKY_APP_PASSWORD set and valid?)",
+            ),
+            Error::Feed(e) => write!(f, "Failed to fetch suggested feeds: {e}"),
+            Error::Http(e) => write!(f, "HTTP error: {e}"),
+            Error::Labeler(e) => write!(f, "Failed to fetch labelers: {e}"),
+        }
+    }
+}
+
 impl From<reqwest::Error> for Error {
     fn from(e: reqwest::Error) -> Self {
         Error::Http(e)
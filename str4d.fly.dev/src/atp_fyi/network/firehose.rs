@@ -1,29 +1,69 @@
-use std::{collections::VecDeque, str::FromStr, sync::OnceLock};
+use std::{
+    collections::VecDeque,
+    str::FromStr,
+    sync::{Arc, OnceLock},
+};
 
 use anyhow::Context;
 use tokio::{sync::RwLock, time};
 use tracing::error;
 
+use crate::{
+    config::Config,
+    util::{supervisor::ServiceHandle, upstream},
+};
+
 static TRACKER: OnceLock<RwLock<MetricsTracker>> = OnceLock::new();
 
 const ONE_MINUTE: time::Duration = time::Duration::from_secs(60);
 const ONE_DAY: time::Duration = time::Duration::from_secs(24 * 60 * 60);
 
-pub(crate) async fn monitor(client: reqwest::Client) -> anyhow::Result<()> {
-    let _ = TRACKER.set(RwLock::new(MetricsTracker::init(&client).await?));
+/// The name under which the monitor is supervised.
+pub(crate) const SERVICE: &str = "firehose monitor";
+
+/// Tracks the firehose rates until the service is cancelled.
+///
+/// Returns an error if the initial metrics can't be fetched, so that the supervisor
+/// retries later instead of the rates being missing until the next restart.
+pub(crate) async fn monitor(
+    client: upstream::Client,
+    config: Arc<Config>,
+    service: ServiceHandle,
+) -> anyhow::Result<()> {
+    let client = client.named("firehose_counts");
+    let url = &config.upstreams.firehose_counts;
+    let tracker = tokio::select! {
+        tracker = MetricsTracker::init(&client, url) => tracker?,
+        _ = service.cancelled() => return Ok(()),
+    };
+    match TRACKER.get() {
+        // We were restarted; start tracking afresh.
+        Some(existing) => *existing.write().await = tracker,
+        None => {
+            let _ = TRACKER.set(RwLock::new(tracker));
+        }
+    }
+    service.report_success();
 
     let mut interval = time::interval(ONE_MINUTE);
     // We already queried the metrics above, so don't immediately re-query them.
     interval.tick().await;
 
     loop {
-        let now = interval.tick().await;
-        match FirehoseCount::fetch(&client).await {
-            Err(e) => error!("Failed to fetch firehose metrics: {e}"),
+        let now = tokio::select! {
+            now = interval.tick() => now,
+            _ = service.cancelled() => return Ok(()),
+        };
+        match FirehoseCount::fetch(&client, url).await {
+            Err(e) => {
+                error!("Failed to fetch firehose metrics: {e}");
+                service.report_error(&e);
+            }
             Ok(data) => {
                 if let Some(tracker) = TRACKER.get() {
                     tracker.write().await.accumulate(now, data);
                 }
+                service.report_success();
             }
         }
     }
@@ -298,8 +338,8 @@ struct MetricsTracker {
 }
 
 impl MetricsTracker {
-    async fn init(client: &reqwest::Client) -> anyhow::Result<Self> {
-        let data = FirehoseCount::fetch(&client)
+    async fn init(client: &upstream::Client, url: &str) -> anyhow::Result<Self> {
+        let data = FirehoseCount::fetch(client, url)
             .await
             .with_context(|| "Failed to fetch firehose metrics")?;
 
@@ -601,10 +641,10 @@ impl FromStr for FirehoseCount {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut metrics = FirehoseCount::default();
         for line in s.lines() {
-            if let Some((metric, value)) = line.split_once(' ') {
-                if let Ok(count) = value.parse::<u64>() {
-                    record_metrics!(metrics, metric, count);
-                }
+            if let Some((metric, value)) = line.split_once(' ')
+                && let Ok(count) = value.parse::<u64>()
+            {
+                record_metrics!(metrics, metric, count);
             }
         }
         Ok(metrics)
@@ -612,9 +652,9 @@ impl FromStr for FirehoseCount {
 }
 
 impl FirehoseCount {
-    async fn fetch(client: &reqwest::Client) -> anyhow::Result<Self> {
+    async fn fetch(client: &upstream::Client, url: &str) -> anyhow::Result<Self> {
         let data = client
-            .get("http://app.process.str4d-bots.internal:9000")
+            .get(url)
             .send()
             .await?
             .error_for_status()?
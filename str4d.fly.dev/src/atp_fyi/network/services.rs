@@ -1,17 +1,23 @@
 use std::collections::{HashMap, HashSet};
-use std::env;
 
 use atrium_api::agent::atp_agent::{AtpAgent, store::MemorySessionStore};
-use atrium_xrpc_client::reqwest::{ReqwestClient, ReqwestClientBuilder};
+
 use tracing::warn;
 
 use super::Error;
+use crate::{
+    config::Config,
+    util::upstream::{self, XrpcClient},
+};
 
 mod feed;
 mod labeler;
 mod pds;
 
-pub(super) async fn enumerate(client: &reqwest::Client) -> Result<Network, Error> {
+pub(super) async fn enumerate(
+    client: &upstream::Client,
+    config: &Config,
+) -> Result<Network, Error> {
     // Hard-coded list of known relays (they aren't discoverable).
     let relays = vec![
         Relay::new("Bluesky Relay US East", "US", "relay1.us-east.bsky.network"),
@@ -35,11 +41,11 @@ pub(super) async fn enumerate(client: &reqwest::Client) -> Result<Network, Error
         Relay::new("bnewbold Demo Relay US", "US", "relay-ovh.demo.bsky.dev"),
     ];
 
-    let bsky = sign_in(client).await?;
+    let bsky = sign_in(client, config).await?;
 
     let mut pdss = HashMap::new();
     for (relay_index, relay) in relays.iter().enumerate() {
-        let relay_pdss = match pds::enumerate(client, relay).await {
+        let relay_pdss = match pds::enumerate(client, &config.upstreams, relay).await {
             Ok(pdss) => pdss,
             Err(e) => {
                 warn!("Failed to enumerate PDSs on {}: {:?}", relay.name, e);
@@ -57,7 +63,7 @@ pub(super) async fn enumerate(client: &reqwest::Client) -> Result<Network, Error
         }
     }
 
-    let labelers = labeler::enumerate(client, &bsky).await?;
+    let labelers = labeler::enumerate(client, &config.upstreams, &bsky).await?;
     let feeds = feed::enumerate(&bsky).await?;
 
     Ok(Network {
@@ -69,19 +75,24 @@ pub(super) async fn enumerate(client: &reqwest::Client) -> Result<Network, Error
 }
 
 async fn sign_in(
-    client: &reqwest::Client,
-) -> Result<AtpAgent<MemorySessionStore, ReqwestClient>, Error> {
-    let password = env::var("BLUESKY_APP_PASSWORD").map_err(|_| Error::BlueskyAuthRequired)?;
+    client: &upstream::Client,
+    config: &Config,
+) -> Result<AtpAgent<MemorySessionStore, XrpcClient>, Error> {
+    let password = config
+        .bluesky
+        .app_password
+        .as_ref()
+        .ok_or(Error::BlueskyAuthRequired)?;
 
     // Sign in to Bluesky
     let client = AtpAgent::new(
-        ReqwestClientBuilder::new("https://bsky.social")
-            .client(client.clone())
-            .build(),
+        client
+            .named("bluesky_pds")
+            .xrpc(&config.upstreams.bluesky_pds),
         MemorySessionStore::default(),
     );
     client
-        .login("str4d.bsky.social", &password)
+        .login(&config.bluesky.handle, password.expose())
         .await
         .map_err(|e| {
             tracing::error!("Failed to log in: {}", e);
@@ -118,6 +129,7 @@ impl Relay {
     }
 }
 
+#[allow(dead_code)]
 #[derive(Debug)]
 pub(super) struct Pds {
     pub(super) relays: HashSet<usize>,
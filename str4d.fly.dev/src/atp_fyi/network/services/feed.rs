@@ -2,12 +2,12 @@ use atrium_api::{
     agent::atp_agent::{AtpAgent, store::MemorySessionStore},
     app::bsky::feed::get_suggested_feeds,
 };
-use atrium_xrpc_client::reqwest::ReqwestClient;
 
 use super::Error;
+use crate::util::upstream::XrpcClient;
 
 pub(super) async fn enumerate(
-    bsky: &AtpAgent<MemorySessionStore, ReqwestClient>,
+    bsky: &AtpAgent<MemorySessionStore, XrpcClient>,
 ) -> Result<Vec<super::Feed>, Error> {
     let mut feeds = vec![];
     let mut cursor = None;
@@ -3,18 +3,27 @@ use atrium_api::{
     app::bsky::labeler::get_services,
     types::{Union, string::Did},
 };
-use atrium_xrpc_client::reqwest::ReqwestClient;
+
 use chrono::{DateTime, Utc};
 use serde::Deserialize;
 
 use super::Error;
+use crate::{
+    config::Upstreams,
+    util::upstream::{self, XrpcClient},
+};
 
 pub(super) async fn enumerate(
-    client: &reqwest::Client,
-    bsky: &AtpAgent<MemorySessionStore, ReqwestClient>,
+    client: &upstream::Client,
+    upstreams: &Upstreams,
+    bsky: &AtpAgent<MemorySessionStore, XrpcClient>,
 ) -> Result<Vec<super::Labeler>, Error> {
     let response = client
-        .get("https://blue.mackuba.eu/xrpc/blue.feeds.mod.getLabellers")
+        .named("feeds_mod")
+        .get(format!(
+            "{}/xrpc/blue.feeds.mod.getLabellers",
+            upstreams.feeds_mod,
+        ))
         .send()
         .await?
         .error_for_status()?
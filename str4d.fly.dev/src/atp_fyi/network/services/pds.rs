@@ -1,12 +1,15 @@
 use serde::Deserialize;
 
 use super::{Error, Pds, Relay};
+use crate::{config::Upstreams, util::upstream};
 
 pub(super) async fn enumerate(
-    client: &reqwest::Client,
+    client: &upstream::Client,
+    upstreams: &Upstreams,
     relay: &Relay,
 ) -> Result<Vec<(String, Pds)>, Error> {
-    let response = list_hosts(client, relay, None).await?;
+    let base_url = upstreams.relay(relay.host);
+    let response = list_hosts(client, &base_url, None).await?;
     let mut pdss = response
         .hosts
         .into_iter()
@@ -15,7 +18,7 @@ pub(super) async fn enumerate(
 
     let mut cursor = response.cursor;
     while cursor.is_some() {
-        let response = list_hosts(client, relay, cursor).await?;
+        let response = list_hosts(client, &base_url, cursor).await?;
         pdss.extend(response.hosts.into_iter().map(|host| host.into()));
         cursor = response.cursor;
     }
@@ -24,14 +27,14 @@ pub(super) async fn enumerate(
 }
 
 async fn list_hosts(
-    client: &reqwest::Client,
-    relay: &Relay,
+    client: &upstream::Client,
+    base_url: &str,
     cursor: Option<String>,
 ) -> Result<ListHostsResponse, Error> {
     Ok(client
+        .named("relay")
         .get(format!(
-            "https://{}/xrpc/com.atproto.sync.listHosts?limit=1000{}",
-            relay.host,
+            "{base_url}/xrpc/com.atproto.sync.listHosts?limit=1000{}",
             cursor.map(|s| format!("&cursor={s}")).unwrap_or_default()
         ))
         .send()
@@ -47,6 +50,7 @@ struct ListHostsResponse {
     hosts: Vec<Host>,
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct Host {
@@ -0,0 +1,478 @@
+//! Server configuration.
+//!
+//! Configuration is loaded once at startup. Values are taken from (in increasing order
+//! of precedence):
+//! - the defaults below;
+//! - the TOML file named by the `CONFIG_FILE` environment variable, if set;
+//! - individual environment variables, documented on each field.
+//!
+//! ```toml
+//! http_port = 8080
+//! metrics_port = 9091
+//! hosts_file = "hosts.toml"
+//! log_format = "json"
+//! trusted_proxies = ["172.16.0.0/12"]
+//! max_concurrent_requests = 64
+//!
+//! [github]
+//! api_key = "..."
+//!
+//! [bluesky]
+//! handle = "str4d.bsky.social"
+//! app_password = "..."
+//!
+//! [upstreams]
+//! datatracker = "http://localhost:3000"
+//!
+//! [outbound]
+//! timeout_secs = 10
+//! retries = 0
+//!
+//! [recording]
+//! mode = "replay"
+//! dir = "recordings"
+//!
+//! [cache]
+//! dir = "/data/cache"
+//!
+//! [tracing]
+//! otlp_endpoint = "http://localhost:4318"
+//! ```
+
+use std::{
+    env, fmt, fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde::Deserialize;
+
+use crate::util::client_ip::TrustedProxies;
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Config {
+    /// The port on which we serve our sites (`HTTP_PORT`).
+    pub(crate) http_port: u16,
+    /// The port on which we serve Prometheus metrics (`METRICS_PORT`).
+    pub(crate) metrics_port: u16,
+    /// The file listing the hosts we serve (`HOSTS_CONFIG`).
+    pub(crate) hosts_file: PathBuf,
+    /// Whether to run background services like the firehose monitor
+    /// (`BACKGROUND_SERVICES`).
+    ///
+    /// These depend on internal services that aren't reachable from a development
+    /// machine, so they are disabled by default under `cargo run`.
+    pub(crate) background_services: bool,
+    /// If set, all requests are handled as if they were for this host (`TEST_HOST`).
+    pub(crate) test_host: Option<String>,
+    /// The format that logs are written in (`LOG_FORMAT`).
+    pub(crate) log_format: LogFormat,
+    /// The networks of the proxies in front of us, which we trust to tell us the IP
+    /// addresses of our clients (`TRUSTED_PROXIES`, comma-separated). By default we
+    /// trust no one, and use the address of each connection's peer.
+    pub(crate) trusted_proxies: TrustedProxies,
+    /// The most requests we handle at once (`MAX_CONCURRENT_REQUESTS`). Requests beyond
+    /// this are turned away with `503 Service Unavailable`, rather than queued.
+    ///
+    /// This is kept below the `hard_limit` in `fly.toml`, so that we shed load before
+    /// fly.io's proxy does (which it does less gracefully).
+    pub(crate) max_concurrent_requests: usize,
+    pub(crate) github: GitHub,
+    pub(crate) bluesky: Bluesky,
+    pub(crate) upstreams: Upstreams,
+    pub(crate) outbound: Outbound,
+    pub(crate) recording: Recording,
+    pub(crate) cache: Cache,
+    pub(crate) tracing: Tracing,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct GitHub {
+    /// API key for the GitHub GraphQL API (`GITHUB_API_KEY`).
+    pub(crate) api_key: Option<Secret>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Bluesky {
+    /// The account we sign in to Bluesky with (`BLUESKY_HANDLE`).
+    pub(crate) handle: String,
+    /// An app password for `handle` (`BLUESKY_APP_PASSWORD`).
+    pub(crate) app_password: Option<Secret>,
+}
+
+/// Base URLs of the services we fetch data from.
+///
+/// These can be pointed at local stand-ins for development and testing. Each can also be
+/// set with an `UPSTREAM_<FIELD>` environment variable (e.g. `UPSTREAM_DATATRACKER`).
+///
+/// | Field             | Default                                       | Used by                                |
+/// |-------------------|-----------------------------------------------|----------------------------------------|
+/// | `bluesky_pds`     | `https://bsky.social`                         | `siso_dev`, `atp_fyi::network` sign-in |
+/// | `bluesky_appview` | `https://public.api.bsky.app`                 | `cryptography_social`                  |
+/// | `bluesky_cdn`     | `https://cdn.bsky.app`                        | `siso_dev` (image links in pages)      |
+/// | `datatracker`     | `https://datatracker.ietf.org`                | `rfc_observer::ietf`                   |
+/// | `eprint_authors`  | `http://app.process.str4d-bots.internal:9001` | `cryptography_social`                  |
+/// | `feeds_mod`       | `https://blue.mackuba.eu`                     | `atp_fyi::network` labelers            |
+/// | `firehose_counts` | `http://app.process.str4d-bots.internal:9000` | `atp_fyi::network::firehose`           |
+/// | `github_graphql`  | `https://api.github.com/graphql`              | `util::github`                         |
+/// | `relay`           | `https://{host}`                              | `atp_fyi::network` PDS enumeration     |
+///
+/// Cached upstream data is keyed by `Upstreams`, so that servers configured with
+/// different upstreams (e.g. concurrent tests) don't share results.
+///
+/// `util::github` is used for the atp.fyi roadmap and `rfc_observer::{go, rust}`. `relay`
+/// is a template: `{host}` is replaced with the host name of each known relay.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Upstreams {
+    pub(crate) bluesky_pds: String,
+    pub(crate) bluesky_appview: String,
+    pub(crate) bluesky_cdn: String,
+    pub(crate) datatracker: String,
+    pub(crate) eprint_authors: String,
+    pub(crate) feeds_mod: String,
+    pub(crate) firehose_counts: String,
+    pub(crate) github_graphql: String,
+    pub(crate) relay: String,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum LogFormat {
+    /// Human-readable text.
+    #[default]
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+/// How we make requests to upstreams (see `util::upstream`).
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Outbound {
+    /// How long we wait to connect to an upstream, in seconds
+    /// (`OUTBOUND_CONNECT_TIMEOUT_SECS`).
+    pub(crate) connect_timeout_secs: u64,
+    /// How long we wait for each response from an upstream, including its body, in
+    /// seconds (`OUTBOUND_TIMEOUT_SECS`).
+    pub(crate) timeout_secs: u64,
+    /// How many times we retry a `GET` request that failed or got a `5xx` response
+    /// (`OUTBOUND_RETRIES`).
+    pub(crate) retries: u32,
+    /// How many requests in a row must fail (after retries) before we stop sending
+    /// requests to an upstream for a while (`OUTBOUND_BREAKER_THRESHOLD`). If zero, we
+    /// never stop.
+    pub(crate) breaker_threshold: u32,
+    /// How long we stop sending requests to a failing upstream for, in seconds
+    /// (`OUTBOUND_BREAKER_COOLDOWN_SECS`).
+    pub(crate) breaker_cooldown_secs: u64,
+}
+
+/// Recording and replaying of upstream traffic (see `util::upstream`).
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Recording {
+    /// Whether to record or replay upstream traffic (`RECORDING_MODE`). If unset, we
+    /// just talk to the upstreams.
+    pub(crate) mode: Option<RecordingMode>,
+    /// The directory that recordings are written to and read from (`RECORDING_DIR`).
+    pub(crate) dir: PathBuf,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum RecordingMode {
+    /// Send requests to upstreams, and record their responses.
+    Record,
+    /// Serve recorded responses instead of sending requests to upstreams.
+    Replay,
+}
+
+/// Persistent caching of expensive upstream data (see `util::cache`).
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Cache {
+    /// The directory that cached data is persisted to (`CACHE_DIR`). If unset, cached
+    /// data is only kept in memory, and is lost on restart.
+    pub(crate) dir: Option<PathBuf>,
+}
+
+/// Export of traces to an OpenTelemetry collector (see `util::logging`).
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Tracing {
+    /// The base URL of an OTLP/HTTP collector to export traces to (`OTLP_ENDPOINT`),
+    /// e.g. `http://localhost:4318`. If unset, traces aren't exported.
+    pub(crate) otlp_endpoint: Option<String>,
+    /// Which spans are exported (`OTLP_FILTER`), with the same syntax as `RUST_LOG`.
+    /// By default this includes our debug-level spans for cache lookups and upstream
+    /// requests.
+    pub(crate) filter: String,
+}
+
+impl Upstreams {
+    /// Returns the base URL for the relay with the given host name.
+    pub(crate) fn relay(&self, host: &str) -> String {
+        self.relay.replace("{host}", host)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            http_port: 8080,
+            metrics_port: 9091,
+            hosts_file: "hosts.toml".into(),
+            background_services: env::var_os("CARGO").is_none(),
+            test_host: None,
+            log_format: LogFormat::default(),
+            trusted_proxies: TrustedProxies::default(),
+            max_concurrent_requests: 64,
+            github: GitHub::default(),
+            bluesky: Bluesky::default(),
+            upstreams: Upstreams::default(),
+            outbound: Outbound::default(),
+            recording: Recording::default(),
+            cache: Cache::default(),
+            tracing: Tracing::default(),
+        }
+    }
+}
+
+impl Default for Upstreams {
+    fn default() -> Self {
+        Self {
+            bluesky_pds: "https://bsky.social".into(),
+            bluesky_appview: "https://public.api.bsky.app".into(),
+            bluesky_cdn: "https://cdn.bsky.app".into(),
+            datatracker: "https://datatracker.ietf.org".into(),
+            eprint_authors: "http://app.process.str4d-bots.internal:9001".into(),
+            feeds_mod: "https://blue.mackuba.eu".into(),
+            firehose_counts: "http://app.process.str4d-bots.internal:9000".into(),
+            github_graphql: "https://api.github.com/graphql".into(),
+            relay: "https://{host}".into(),
+        }
+    }
+}
+
+impl Default for Outbound {
+    fn default() -> Self {
+        Self {
+            connect_timeout_secs: 5,
+            timeout_secs: 30,
+            retries: 2,
+            breaker_threshold: 5,
+            breaker_cooldown_secs: 30,
+        }
+    }
+}
+
+impl Default for Recording {
+    fn default() -> Self {
+        Self {
+            mode: None,
+            dir: "recordings".into(),
+        }
+    }
+}
+
+impl Default for Tracing {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: None,
+            filter: "info,str4d_fly_dev=debug".into(),
+        }
+    }
+}
+
+impl Default for Bluesky {
+    fn default() -> Self {
+        Self {
+            handle: "str4d.bsky.social".into(),
+            app_password: None,
+        }
+    }
+}
+
+/// A configuration value that must not be logged.
+#[derive(Clone, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct Secret(String);
+
+impl Secret {
+    pub(crate) fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(..)")
+    }
+}
+
+impl Config {
+    /// Loads the configuration from `CONFIG_FILE` (if set) and the environment.
+    pub(crate) fn load() -> Result<Self, Error> {
+        let mut config = match env::var_os("CONFIG_FILE") {
+            Some(path) => Self::from_file(Path::new(&path))?,
+            None => Self::default(),
+        };
+
+        override_from_env("HTTP_PORT", &mut config.http_port)?;
+        override_from_env("METRICS_PORT", &mut config.metrics_port)?;
+        override_from_env("HOSTS_CONFIG", &mut config.hosts_file)?;
+        override_from_env("BACKGROUND_SERVICES", &mut config.background_services)?;
+        optional_from_env("TEST_HOST", &mut config.test_host)?;
+        override_from_env("LOG_FORMAT", &mut config.log_format)?;
+        override_from_env("TRUSTED_PROXIES", &mut config.trusted_proxies)?;
+        override_from_env(
+            "MAX_CONCURRENT_REQUESTS",
+            &mut config.max_concurrent_requests,
+        )?;
+        optional_from_env("GITHUB_API_KEY", &mut config.github.api_key)?;
+        override_from_env("BLUESKY_HANDLE", &mut config.bluesky.handle)?;
+        optional_from_env("BLUESKY_APP_PASSWORD", &mut config.bluesky.app_password)?;
+
+        let upstreams = &mut config.upstreams;
+        override_from_env("UPSTREAM_BLUESKY_PDS", &mut upstreams.bluesky_pds)?;
+        override_from_env("UPSTREAM_BLUESKY_APPVIEW", &mut upstreams.bluesky_appview)?;
+        override_from_env("UPSTREAM_BLUESKY_CDN", &mut upstreams.bluesky_cdn)?;
+        override_from_env("UPSTREAM_DATATRACKER", &mut upstreams.datatracker)?;
+        override_from_env("UPSTREAM_EPRINT_AUTHORS", &mut upstreams.eprint_authors)?;
+        override_from_env("UPSTREAM_FEEDS_MOD", &mut upstreams.feeds_mod)?;
+        override_from_env("UPSTREAM_FIREHOSE_COUNTS", &mut upstreams.firehose_counts)?;
+        override_from_env("UPSTREAM_GITHUB_GRAPHQL", &mut upstreams.github_graphql)?;
+        override_from_env("UPSTREAM_RELAY", &mut upstreams.relay)?;
+
+        let outbound = &mut config.outbound;
+        override_from_env(
+            "OUTBOUND_CONNECT_TIMEOUT_SECS",
+            &mut outbound.connect_timeout_secs,
+        )?;
+        override_from_env("OUTBOUND_TIMEOUT_SECS", &mut outbound.timeout_secs)?;
+        override_from_env("OUTBOUND_RETRIES", &mut outbound.retries)?;
+        override_from_env(
+            "OUTBOUND_BREAKER_THRESHOLD",
+            &mut outbound.breaker_threshold,
+        )?;
+        override_from_env(
+            "OUTBOUND_BREAKER_COOLDOWN_SECS",
+            &mut outbound.breaker_cooldown_secs,
+        )?;
+
+        optional_from_env("RECORDING_MODE", &mut config.recording.mode)?;
+        override_from_env("RECORDING_DIR", &mut config.recording.dir)?;
+
+        optional_from_env("CACHE_DIR", &mut config.cache.dir)?;
+
+        optional_from_env("OTLP_ENDPOINT", &mut config.tracing.otlp_endpoint)?;
+        override_from_env("OTLP_FILTER", &mut config.tracing.filter)?;
+
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> Result<Self, Error> {
+        let contents = fs::read_to_string(path).map_err(|e| Error::Io(path.into(), e))?;
+        toml::from_str(&contents).map_err(|e| Error::Parse(path.into(), e))
+    }
+
+    /// Returns the secrets that the server needs in order to serve every feature of every
+    /// site, and whether each of them is configured.
+    pub(crate) fn secrets(&self) -> [(&'static str, bool); 2] {
+        [
+            ("BLUESKY_APP_PASSWORD", self.bluesky.app_password.is_some()),
+            ("GITHUB_API_KEY", self.github.api_key.is_some()),
+        ]
+    }
+
+    /// Logs a warning for each missing secret, along with the features it disables.
+    pub(crate) fn report_missing_secrets(&self) {
+        if self.github.api_key.is_none() {
+            tracing::warn!(
+                "GITHUB_API_KEY is not set; disabling the atp.fyi roadmap, go.rfc.observer, and rust.rfc.observer",
+            );
+        }
+        if self.bluesky.app_password.is_none() {
+            tracing::warn!("BLUESKY_APP_PASSWORD is not set; disabling the atp.fyi network map");
+        }
+    }
+}
+
+fn override_from_env<T: FromStr>(var: &'static str, field: &mut T) -> Result<(), Error> {
+    if let Some(value) = optional_env(var)? {
+        *field = value;
+    }
+    Ok(())
+}
+
+fn optional_from_env<T: FromStr>(var: &'static str, field: &mut Option<T>) -> Result<(), Error> {
+    if let Some(value) = optional_env(var)? {
+        *field = Some(value);
+    }
+    Ok(())
+}
+
+/// Parses the given environment variable, treating an empty value as unset.
+fn optional_env<T: FromStr>(var: &'static str) -> Result<Option<T>, Error> {
+    match env::var(var) {
+        Ok(value) if value.is_empty() => Ok(None),
+        Ok(value) => value.parse().map(Some).map_err(|_| Error::InvalidEnv(var)),
+        Err(env::VarError::NotPresent) => Ok(None),
+        Err(env::VarError::NotUnicode(_)) => Err(Error::InvalidEnv(var)),
+    }
+}
+
+impl FromStr for Secret {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Secret(s.into()))
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(()),
+        }
+    }
+}
+
+impl FromStr for RecordingMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "record" => Ok(RecordingMode::Record),
+            "replay" => Ok(RecordingMode::Replay),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum Error {
+    InvalidEnv(&'static str),
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidEnv(var) => write!(f, "{var} environment variable is invalid"),
+            Error::Io(path, e) => write!(f, "Failed to read {}: {e}", path.display()),
+            Error::Parse(path, e) => write!(f, "Failed to parse {}: {e}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
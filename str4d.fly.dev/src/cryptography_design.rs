@@ -2,8 +2,16 @@ use askama::Template;
 use askama_web::WebTemplate;
 use axum::{Router, routing::get};
 
+use crate::util::error::{ErrorPages, Problem};
+
 pub(crate) fn build() -> Router {
-    Router::new().route("/", get(index))
+    ErrorPages::new(|error| ErrorPage { error }).apply(Router::new().route("/", get(index)))
+}
+
+#[derive(Template)]
+#[template(path = "cryptography.design/error.html")]
+struct ErrorPage {
+    error: Problem,
 }
 
 #[derive(Template, WebTemplate)]
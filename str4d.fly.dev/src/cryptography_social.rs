@@ -1,15 +1,39 @@
 use std::collections::HashMap;
+use std::sync::{Arc, LazyLock};
 use std::time::Duration;
 
 use anyhow::Context;
 use askama::Template;
 use askama_web::WebTemplate;
-use axum::{Router, routing::get};
-use cached::proc_macro::cached;
-use serde::Deserialize;
+use axum::{Extension, Router, routing::get};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{Config, Upstreams},
+    util::{
+        cache::Cache,
+        error::{ErrorPages, Problem},
+        http_cache::CachePolicy,
+        supervisor::Supervisor,
+        upstream,
+    },
+};
+
+static EPRINT_AUTHORS: LazyLock<Cache<Vec<User>, Arc<anyhow::Error>>> =
+    LazyLock::new(|| Cache::new("cryptography.social-authors", 1, Duration::from_secs(60)));
 
 pub(crate) fn build() -> Router {
-    Router::new().route("/", get(index))
+    let router = Router::new().route(
+        "/",
+        get(index).layer(CachePolicy::public(EPRINT_AUTHORS.ttl())),
+    );
+    ErrorPages::new(|error| ErrorPage { error }).apply(router)
+}
+
+#[derive(Template)]
+#[template(path = "cryptography.social/error.html")]
+struct ErrorPage {
+    error: Problem,
 }
 
 #[derive(Template, WebTemplate)]
@@ -18,9 +42,19 @@ struct Index {
     users: Vec<User>,
 }
 
-async fn index() -> Index {
-    let users = match fetch_eprint_authors().await {
-        Ok(users) => users,
+async fn index(
+    Extension(client): Extension<upstream::Client>,
+    Extension(config): Extension<Arc<Config>>,
+    Extension(supervisor): Extension<Supervisor>,
+) -> Index {
+    let (dir, key) = (config.cache.dir.clone(), authors_key(&config.upstreams));
+    let res = EPRINT_AUTHORS
+        .get(&supervisor.clone(), dir, key, move || {
+            fetch_eprint_authors(client, config, supervisor)
+        })
+        .await;
+    let users = match res {
+        Ok(users) => users.value,
         Err(e) => {
             tracing::error!("Failed to fetch ePrint authors: {e}");
             vec![]
@@ -30,7 +64,11 @@ async fn index() -> Index {
     Index { users }
 }
 
-#[derive(Clone)]
+fn authors_key(upstreams: &Upstreams) -> String {
+    format!("{}|{}", upstreams.eprint_authors, upstreams.bluesky_appview)
+}
+
+#[derive(Clone, Deserialize, Serialize)]
 struct User {
     name: String,
     profile: String,
@@ -47,12 +85,25 @@ impl User {
     }
 }
 
-#[cached(result = true, time = 60)]
-async fn fetch_eprint_authors() -> Result<Vec<User>, anyhow::Error> {
-    let client = reqwest::ClientBuilder::new().use_rustls_tls().build()?;
+async fn fetch_eprint_authors(
+    client: upstream::Client,
+    config: Arc<Config>,
+    supervisor: Supervisor,
+) -> Result<Vec<User>, Arc<anyhow::Error>> {
+    let res = query_eprint_authors(&client, &config.upstreams).await;
+    supervisor
+        .data_sources()
+        .record("cryptography.social authors", &res);
+    res.map_err(Arc::new)
+}
 
+async fn query_eprint_authors(
+    client: &upstream::Client,
+    upstreams: &Upstreams,
+) -> Result<Vec<User>, anyhow::Error> {
     let list = client
-        .get("http://app.process.str4d-bots.internal:9001")
+        .named("eprint_authors")
+        .get(&upstreams.eprint_authors)
         .send()
         .await
         .context("Failed to fetch authors list")?
@@ -63,8 +114,10 @@ async fn fetch_eprint_authors() -> Result<Vec<User>, anyhow::Error> {
 
     let mut authors = vec![];
     for chunk in list.authors.chunks(25) {
-        let mut request_url =
-            String::from("https://public.api.bsky.app/xrpc/app.bsky.actor.getProfiles?actors=");
+        let mut request_url = format!(
+            "{}/xrpc/app.bsky.actor.getProfiles?actors=",
+            upstreams.bluesky_appview,
+        );
         for (i, author) in chunk.iter().enumerate() {
             if i > 0 {
                 request_url += "&actors=";
@@ -73,6 +126,7 @@ async fn fetch_eprint_authors() -> Result<Vec<User>, anyhow::Error> {
         }
 
         let response = client
+            .named("bluesky_appview")
             .get(request_url)
             .send()
             .await
@@ -1,49 +1,75 @@
-use std::env;
-use std::net::{IpAddr, Ipv6Addr};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
 
-use axum::{Extension, ServiceExt};
-use metrics_exporter_prometheus::PrometheusBuilder;
+use axum::{Extension, ServiceExt, middleware};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder};
 use tokio::net::TcpListener;
 use tower_http::trace::TraceLayer;
-use tracing_subscriber::fmt::format::FmtSpan;
 
+mod config;
 mod util;
 
 mod atp_fyi;
 mod cryptography_design;
 mod cryptography_social;
-mod jackgrigg_com;
 mod rfc_observer;
 mod siso_dev;
 mod sssh;
 mod str4d_xyz;
 
+#[cfg(test)]
+mod tests;
+
+/// How long we wait for in-flight requests and background tasks to finish after being
+/// asked to shut down. fly.io kills us 5 seconds after sending SIGINT (`kill_timeout`).
+const SHUTDOWN_DEADLINE: Duration = Duration::from_secs(4);
+
 #[tokio::main]
 async fn main() {
     println!("Printing something + 3 as early as possible so fly.io sees it.");
 
-    // Filter traces based on the RUST_LOG env var, or, if it's not set,
-    // default to show info-level details.
-    let filter = std::env::var("RUST_LOG").unwrap_or_else(|_| "info".to_owned());
+    // The configuration determines how we log, so we can only report a problem with it
+    // once logging has started.
+    let config = config::Config::load();
+    // Flushes any remaining traces when we return.
+    let _traces = match &config {
+        Ok(config) => util::logging::init(config.log_format, &config.tracing),
+        Err(_) => util::logging::init(config::LogFormat::default(), &config::Tracing::default()),
+    };
 
-    tracing_subscriber::fmt()
-        // Use the filter we built above to determine which traces to record.
-        .with_env_filter(filter)
-        // Record an event when each span closes. This can be used to time our
-        // routes' durations!
-        .with_span_events(FmtSpan::CLOSE)
-        .init();
+    let config = match config {
+        Ok(config) => Arc::new(config),
+        Err(e) => {
+            tracing::error!("Invalid configuration: {e}");
+            return;
+        }
+    };
+    config.report_missing_secrets();
+    if let Some(dir) = &config.cache.dir {
+        tracing::info!("Persisting cached data to {}", dir.display());
+    }
 
     tracing::info!("Starting metrics");
     if let Err(e) = PrometheusBuilder::new()
-        .with_http_listener(([0, 0, 0, 0, 0, 0, 0, 0], 9091))
-        .install()
+        .with_http_listener((Ipv6Addr::UNSPECIFIED, config.metrics_port))
+        .set_buckets_for_metric(
+            Matcher::Full(util::HTTP_REQUEST_DURATION.into()),
+            util::HTTP_REQUEST_DURATION_BUCKETS,
+        )
+        .and_then(|builder| {
+            builder.set_buckets_for_metric(
+                Matcher::Full(util::upstream::UPSTREAM_REQUEST_DURATION.into()),
+                util::upstream::UPSTREAM_REQUEST_DURATION_BUCKETS,
+            )
+        })
+        .and_then(|builder| builder.install())
     {
         tracing::error!("Failed to install metrics server: {}", e);
     };
 
     // Client for outbound HTTP requests.
-    let client = match reqwest::Client::builder().user_agent("atp.fyi").build() {
+    let client = match util::upstream::Client::new(&config.recording, &config.outbound) {
         Ok(client) => client,
         Err(e) => {
             tracing::error!("Failed to build an HTTP client: {e}");
@@ -51,49 +77,129 @@ async fn main() {
         }
     };
 
+    let shutdown = util::shutdown::Shutdown::new();
+    shutdown.listen_for_signals();
+
     // Set up background services.
     tracing::info!("Starting background services");
-    if env::var("CARGO").is_err() {
+    let supervisor = util::supervisor::Supervisor::new(shutdown.clone());
+    if config.background_services {
+        for job in warming_jobs(&client, &config, &supervisor) {
+            job.schedule(&supervisor);
+        }
+
         let client = client.clone();
-        tokio::spawn(async { atp_fyi::network::firehose::monitor(client).await });
+        let config = config.clone();
+
+        supervisor.supervise(atp_fyi::network::firehose::SERVICE, move |service| {
+            atp_fyi::network::firehose::monitor(client.clone(), config.clone(), service)
+        });
     }
 
     tracing::info!("Starting server");
-    let app = util::Multiplexer::new()
-        .add("www.jackgrigg.com", ["jackgrigg.com"], jackgrigg_com::www())
-        .handle("blog.jackgrigg.com", jackgrigg_com::blog())
-        .add("str4d.xyz", ["www.str4d.xyz"], str4d_xyz::build())
-        .add("siso.dev", ["www.siso.dev"], siso_dev::build())
-        .add(
-            "cryptography.design",
-            ["www.cryptography.design"],
-            cryptography_design::build(),
-        )
-        .add(
-            "cryptography.social",
-            ["www.cryptography.social"],
-            cryptography_social::build(),
-        )
-        .add("atp.fyi", ["www.atp.fyi"], atp_fyi::build())
-        .add("s-s.sh", ["www.s-s.sh"], sssh::build())
-        .add("rfc.observer", ["www.rfc.observer"], rfc_observer::build())
-        .handle("ietf.rfc.observer", rfc_observer::ietf::build())
-        .handle("go.rfc.observer", rfc_observer::go::build())
-        .handle("rust.rfc.observer", rfc_observer::rust::build())
-        .layer(Extension(client))
-        .layer(util::MetricsLayer::new())
-        .layer(TraceLayer::new_for_http());
+    let app = match build_app(config.clone(), client, supervisor) {
+        Ok(app) => app,
+        Err(e) => {
+            tracing::error!("Invalid hosts configuration: {e}");
+            return;
+        }
+    }
+    .layer(shutdown.layer());
 
-    let addr: (IpAddr, _) = (Ipv6Addr::UNSPECIFIED.into(), 8080);
+    let addr: (IpAddr, _) = (Ipv6Addr::UNSPECIFIED.into(), config.http_port);
     tracing::debug!("Listening on {:?}", addr);
 
     match TcpListener::bind(addr).await {
-        Err(e) => tracing::error!("Failed to bind to listening address: {}", e),
-        Ok(listener) => {
-            let server = axum::serve(listener, app.into_make_service());
+        Err(e) => {
+            tracing::error!("Failed to bind to listening address: {}", e);
+            shutdown.drain(SHUTDOWN_DEADLINE).await;
+            return;
+        }
+        Ok(listener) => shutdown.spawn("HTTP server", |token| async move {
+            // Once shutdown starts, stop accepting connections and wait for in-flight
+            // requests to finish.
+            let server = axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(token.clone().cancelled_owned());
             if let Err(e) = server.await {
                 tracing::error!("Server error: {}", e);
+                token.cancel();
             }
-        }
+        }),
     }
+
+    shutdown.started().await;
+    shutdown.drain(SHUTDOWN_DEADLINE).await;
+}
+
+/// Returns the jobs that keep our cached data warm.
+fn warming_jobs(
+    client: &util::upstream::Client,
+    config: &Arc<config::Config>,
+    supervisor: &util::supervisor::Supervisor,
+) -> Vec<util::scheduler::Job> {
+    vec![
+        atp_fyi::warm_network_map(client, config, supervisor),
+        atp_fyi::warm_roadmap(client, config, supervisor),
+        rfc_observer::go::warm(client, config, supervisor),
+        rfc_observer::ietf::warm(client, config, supervisor),
+        rfc_observer::rust::warm(client, config, supervisor),
+    ]
+}
+
+/// Builds the [`util::Multiplexer`] that serves all of our sites.
+fn build_app(
+    config: Arc<config::Config>,
+    client: util::upstream::Client,
+    supervisor: util::supervisor::Supervisor,
+) -> Result<util::Multiplexer<()>, util::hosts::Error> {
+    let routers = util::hosts::Routers::new()
+        .register("str4d.xyz", str4d_xyz::build())
+        .register("siso.dev", siso_dev::build())
+        .register("cryptography.design", cryptography_design::build())
+        .register("cryptography.social", cryptography_social::build())
+        .register("atp.fyi", atp_fyi::build())
+        .register("s-s.sh", sssh::build())
+        .register("rfc.observer", rfc_observer::build())
+        .register("*.rfc.observer", rfc_observer::unknown())
+        .register(
+            "ietf.rfc.observer",
+            rfc_observer::ietf::build(&config, &client, &supervisor),
+        )
+        .register("go.rfc.observer", rfc_observer::go::build())
+        .register("rust.rfc.observer", rfc_observer::rust::build());
+
+    let app = util::hosts::HostsConfig::load(&config.hosts_file)
+        .and_then(|hosts| util::Multiplexer::from_config(hosts, routers))?
+        // Applied before reserving paths, so that health checks aren't shed.
+        .layer(middleware::from_fn_with_state(
+            util::limits::ConcurrencyLimit::new(config.max_concurrent_requests),
+            util::limits::shed,
+        ))
+        .reserve("/_health", util::health::health())
+        .reserve(
+            "/_ready",
+            util::health::ready(supervisor.clone(), config.clone()),
+        )
+        .test_host(config.test_host.clone())
+        .layer(Extension(client))
+        .layer(Extension(config.clone()))
+        .layer(Extension(supervisor))
+        .layer(util::compression::layer())
+        .layer(util::MetricsLayer::new())
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(util::logging::make_span)
+                .on_response(util::logging::on_response),
+        )
+        // These need to run before the trace layer creates the request's span.
+        .layer(middleware::from_fn_with_state(
+            Arc::new(config.trusted_proxies.clone()),
+            util::client_ip::resolve,
+        ))
+        .layer(middleware::from_fn(util::request_id::assign));
+
+    Ok(app)
 }
@@ -2,6 +2,11 @@ use askama::Template;
 use askama_web::WebTemplate;
 use axum::{Router, routing::get};
 
+use crate::util::{
+    Subdomain,
+    error::{Error, ErrorPages, Problem},
+};
+
 pub(crate) mod common;
 
 pub(crate) mod go;
@@ -9,7 +14,23 @@ pub(crate) mod ietf;
 pub(crate) mod rust;
 
 pub(crate) fn build() -> Router {
-    Router::new().route("/", get(index))
+    error_pages().apply(Router::new().route("/", get(index)))
+}
+
+/// Handles subdomains of rfc.observer that don't have an observer.
+pub(crate) fn unknown() -> Router {
+    error_pages().apply(Router::new().fallback(unknown_observer))
+}
+
+/// The error pages shared by rfc.observer and its observers.
+fn error_pages() -> ErrorPages {
+    ErrorPages::new(|error| ErrorPage { error })
+}
+
+#[derive(Template)]
+#[template(path = "rfc.observer/error.html")]
+struct ErrorPage {
+    error: Problem,
 }
 
 #[derive(Clone, Template, WebTemplate)]
@@ -19,3 +40,7 @@ struct Index {}
 async fn index() -> Index {
     Index {}
 }
+
+async fn unknown_observer(Subdomain(observer): Subdomain) -> Error {
+    Error::not_found().detail(format!("There is no {observer}.rfc.observer (yet)."))
+}
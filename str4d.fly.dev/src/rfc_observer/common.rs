@@ -2,7 +2,7 @@ use std::{collections::BTreeMap, str::FromStr};
 
 use chrono::{Datelike, Months};
 use graphql_client::GraphQLQuery;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use crate::util::github;
 
@@ -170,13 +170,13 @@ pub(super) fn label_events_for_bodied<L: FromStr>(
     ret
 }
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub(super) struct Bucket {
     label: u32,
     count: u64,
 }
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub(super) struct HistogramStats {
     median: u32,
 }
@@ -1,17 +1,33 @@
+use std::sync::{Arc, LazyLock};
 use std::time::Duration;
 
 use askama::Template;
 use askama_web::WebTemplate;
-use axum::{Json, Router, routing::get};
-use cached::proc_macro::cached;
+use axum::{Extension, Router, routing::get};
+
+use crate::{
+    config::Config,
+    util::{
+        cache::{AgedJson, Cache},
+        http_cache::CachePolicy,
+        scheduler::Job,
+        supervisor::Supervisor,
+        upstream,
+    },
+};
 
 mod data;
 mod github;
 
+static PROPOSALS: LazyLock<Cache<data::Data, Arc<github::Error>>> =
+    LazyLock::new(|| Cache::new("go-proposals", 1, Duration::from_secs(600)));
+
 pub(crate) fn build() -> Router {
-    Router::new()
-        .route("/", get(index))
-        .route("/api/data", get(data))
+    let router = Router::new().route("/", get(index)).route(
+        "/api/data",
+        get(data).layer(CachePolicy::public(PROPOSALS.ttl())),
+    );
+    super::error_pages().apply(router)
 }
 
 #[derive(Clone, Template, WebTemplate)]
@@ -22,15 +38,58 @@ async fn index() -> Index {
     Index {}
 }
 
-#[cached(time = 600)]
-async fn data() -> Json<Option<data::Data>> {
-    let data = match self::github::get_proposals().await {
-        Ok(proposals) => Some(data::Data::new(proposals)),
-        Err(e) => {
-            tracing::error!("Failed to get proposals: {}", e);
-            None
-        }
-    };
-
-    Json(data)
+async fn data(
+    Extension(client): Extension<upstream::Client>,
+    Extension(config): Extension<Arc<Config>>,
+    Extension(supervisor): Extension<Supervisor>,
+) -> AgedJson<data::Data> {
+    let (dir, key) = (config.cache.dir.clone(), key(&config));
+    let data = PROPOSALS
+        .read(&supervisor.clone(), dir, key, move || {
+            fetch(client, config, supervisor)
+        })
+        .await;
+
+    AgedJson(data)
+}
+
+fn key(config: &Config) -> String {
+    config.upstreams.github_graphql.clone()
+}
+
+async fn fetch(
+    client: upstream::Client,
+    config: Arc<Config>,
+    supervisor: Supervisor,
+) -> Result<data::Data, Arc<github::Error>> {
+    let res = self::github::get_proposals(&client, &config).await;
+    supervisor
+        .data_sources()
+        .record("go.rfc.observer proposals", &res);
+    res.map(data::Data::new).map_err(Arc::new)
+}
+
+/// Keeps the proposals warm.
+pub(crate) fn warm(
+    client: &upstream::Client,
+    config: &Arc<Config>,
+    supervisor: &Supervisor,
+) -> Job {
+    let (client, config, supervisor) = (client.clone(), config.clone(), supervisor.clone());
+    Job::new(
+        "warm go.rfc.observer",
+        Duration::from_secs(600),
+        move |_| {
+            let (client, config, supervisor) = (client.clone(), config.clone(), supervisor.clone());
+            async move {
+                let (dir, key) = (config.cache.dir.clone(), key(&config));
+                PROPOSALS
+                    .warm(dir, key, || fetch(client, config, supervisor))
+                    .await
+                    .map_err(|e| anyhow::anyhow!("{e}"))?;
+                Ok(())
+            }
+        },
+    )
+    .jitter(Duration::from_secs(60))
 }
@@ -1,7 +1,7 @@
 use std::{collections::BTreeMap, str::FromStr};
 
 use chrono::{DateTime, NaiveDate, Utc};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use crate::rfc_observer::common::{
     Bucket, HistogramStats, LabelEvent, completion_months_histogram,
@@ -17,7 +17,7 @@ const PROPOSALS_TO_IGNORE: &[i64] = &[
     69433, 69844, 70066, 70902, 70946, 71276, 71472, 71627, 71707, 71755, 71980,
 ];
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub(super) struct Proposal {
     pub(super) number: i64,
     title: String,
@@ -65,7 +65,7 @@ impl FromStr for Label {
     }
 }
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub(super) struct Aggregate {
     date: NaiveDate,
     created: u64,
@@ -75,7 +75,7 @@ pub(super) struct Aggregate {
     closed: u64,
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub(super) struct Data {
     pub(super) agg: Vec<Aggregate>,
     pub(super) completed_hist: Vec<Bucket>,
@@ -250,7 +250,7 @@ impl Data {
 
         // Prepare a histogram of "number of proposals completed within X months".
         let (completed_hist, completed_stats) = completion_months_histogram(&closed, |proposal| {
-            (&proposal.created_at, &proposal.closed_at.as_ref().unwrap())
+            (&proposal.created_at, proposal.closed_at.as_ref().unwrap())
         });
 
         Self {
@@ -1,14 +1,18 @@
 use std::fmt;
 
 use crate::{
+    config::Config,
     rfc_observer::common::{IssuesWithLabelsQuery, issues_with_labels_query},
-    util::github,
+    util::{github, upstream},
 };
 
 use super::data::Proposal;
 
-pub(super) async fn get_proposals() -> Result<Vec<Proposal>, Error> {
-    let client = github::Client::new("go.rfc.observer")?;
+pub(super) async fn get_proposals(
+    client: &upstream::Client,
+    config: &Config,
+) -> Result<Vec<Proposal>, Error> {
+    let client = github::Client::new(client, "go.rfc.observer", config)?;
 
     let data = client
         .post_paginated_graphql::<IssuesWithLabelsQuery>(issues_with_labels_query::Variables {
@@ -32,9 +36,8 @@ pub(super) async fn get_proposals() -> Result<Vec<Proposal>, Error> {
         .edges
         .into_iter()
         .flat_map(|issues| issues.into_iter())
-        .map(|e| e.and_then(|edge| edge.node))
-        .flatten()
-        .flat_map(|issue| Proposal::new(issue))
+        .filter_map(|e| e.and_then(|edge| edge.node))
+        .flat_map(Proposal::new)
         .collect::<Vec<_>>();
 
     proposals.sort_by_key(|issue| issue.number);
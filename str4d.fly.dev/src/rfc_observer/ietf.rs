@@ -1,25 +1,83 @@
 use std::sync::Arc;
+use std::time::Duration;
 
+use anyhow::Context;
+
+use crate::{
+    config::Config,
+    util::{
+        cache::{AgedJson, Warming},
+        error::Error,
+        http_cache::CachePolicy,
+        scheduler::Job,
+        supervisor::Supervisor,
+        upstream,
+    },
+};
 use askama::Template;
 use askama_web::WebTemplate;
 use axum::{
-    Json, Router,
+    Router,
     extract::{Path, State},
+    response::{IntoResponse, Response},
     routing::get,
 };
-use hyper::StatusCode;
 
 mod data;
 mod datatracker;
 
-pub(crate) fn build() -> Router {
-    let state = Arc::new(self::datatracker::build_client().expect("should succeed"));
+pub(crate) fn build(config: &Config, client: &upstream::Client, supervisor: &Supervisor) -> Router {
+    let state = Arc::new(self::datatracker::build_client(client, config, supervisor));
 
-    Router::new()
-        .route("/", get(index))
-        .route("/{acronym}", get(group))
-        .route("/api/data/{acronym}", get(data))
-        .with_state(state)
+    let router = Router::new()
+        .route(
+            "/",
+            get(index).layer(CachePolicy::public(self::datatracker::GROUPS.ttl())),
+        )
+        .route(
+            "/{acronym}",
+            get(group).layer(CachePolicy::public(self::datatracker::GROUPS.ttl())),
+        )
+        .route(
+            "/api/data/{acronym}",
+            get(data).layer(CachePolicy::public(self::datatracker::DOCUMENTS.ttl())),
+        )
+        .with_state(state);
+    super::error_pages().apply(router)
+}
+
+/// Keeps the list of groups, and the documents of every active group, warm.
+pub(crate) fn warm(client: &upstream::Client, config: &Config, supervisor: &Supervisor) -> Job {
+    let client = Arc::new(self::datatracker::build_client(client, config, supervisor));
+
+    Job::new(
+        "warm ietf.rfc.observer",
+        Duration::from_secs(60 * 60),
+        move |budget| {
+            let client = client.clone();
+            async move {
+                let (active_groups, _) = self::datatracker::warm_groups(&client)
+                    .await
+                    .context("Failed to get groups")?;
+
+                budget
+                    .run_all(active_groups.into_iter().map(|group| {
+                        let client = client.clone();
+                        async move {
+                            self::datatracker::warm_documents(&client, &group.acronym)
+                                .await
+                                .with_context(|| {
+                                    format!("Failed to get documents for {}", group.acronym)
+                                })?;
+                            Ok(())
+                        }
+                    }))
+                    .await
+            }
+        },
+    )
+    .jitter(Duration::from_secs(5 * 60))
+    .concurrency(4)
 }
 
 #[derive(Clone, Template, WebTemplate)]
@@ -29,17 +87,14 @@ struct Index {
     inactive_groups: Vec<self::datatracker::Group>,
 }
 
-async fn index(State(client): State<Arc<reqwest::Client>>) -> Result<Index, StatusCode> {
-    self::datatracker::get_groups(&client)
+async fn index(State(client): State<Arc<self::datatracker::Client>>) -> Result<Index, Warming> {
+    let (active_groups, inactive_groups) = self::datatracker::get_groups(&client)
         .await
-        .map(|(active_groups, inactive_groups)| Index {
-            active_groups,
-            inactive_groups,
-        })
-        .map_err(|e| {
-            tracing::error!("Failed to get groups: {:?}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
+        .ok_or(Warming)?;
+    Ok(Index {
+        active_groups,
+        inactive_groups,
+    })
 }
 
 #[derive(Clone, Template, WebTemplate)]
@@ -49,29 +104,41 @@ struct Group {
 }
 
 async fn group(
-    State(client): State<Arc<reqwest::Client>>,
+    State(client): State<Arc<self::datatracker::Client>>,
     Path(acronym): Path<String>,
-) -> Result<Group, StatusCode> {
-    self::datatracker::get_group(&client, &acronym)
-        .await
-        .map(|group| Group { group })
-        .map_err(|e| {
-            tracing::error!("Failed to get group: {:?}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
+) -> Result<Group, Response> {
+    let group = find_group(&client, &acronym).await?;
+    Ok(Group { group })
 }
 
 async fn data(
-    State(client): State<Arc<reqwest::Client>>,
+    State(client): State<Arc<self::datatracker::Client>>,
     Path(acronym): Path<String>,
-) -> Json<Option<data::Data>> {
-    let data = match self::datatracker::get_documents(&client, &acronym).await {
-        Ok(documents) => Some(data::Data::new(documents)),
-        Err(e) => {
-            tracing::error!("Failed to get documents: {:?}", e);
-            None
-        }
-    };
+) -> Result<AgedJson<data::Data>, Response> {
+    // Don't start fetching documents for groups that don't exist.
+    find_group(&client, &acronym).await?;
 
-    Json(data)
+    let documents = self::datatracker::get_documents(&client, &acronym).await;
+    Ok(AgedJson(
+        documents.map(|documents| documents.map(data::Data::new)),
+    ))
+}
+
+/// Returns the group named `acronym` from the list of groups.
+async fn find_group(
+    client: &Arc<self::datatracker::Client>,
+    acronym: &str,
+) -> Result<self::datatracker::Group, Response> {
+    let (active_groups, inactive_groups) = self::datatracker::get_groups(client)
+        .await
+        .ok_or_else(|| Warming.into_response())?;
+    active_groups
+        .into_iter()
+        .chain(inactive_groups)
+        .find(|group| group.acronym == acronym)
+        .ok_or_else(|| {
+            Error::not_found()
+                .detail(format!("There is no IETF group named {acronym}."))
+                .into_response()
+        })
 }
@@ -1,13 +1,13 @@
 use std::collections::BTreeMap;
 
 use chrono::{DateTime, NaiveDate, Utc};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use crate::rfc_observer::common::{Bucket, HistogramStats, completion_months_histogram};
 
 use super::datatracker::DocInfo;
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub(super) struct Document {
     name: String,
     title: String,
@@ -82,7 +82,7 @@ impl Data {
             // Mark each revision as a day without changes, so the transition from last
             // draft to published RFC renders correctly.
             for at in &doc.revisions {
-                let _ = day(&mut deltas, &at);
+                let _ = day(&mut deltas, at);
             }
 
             match (doc.expires_at, doc.closed_at) {
@@ -130,7 +130,7 @@ impl Data {
 
         let mut open = documents
             .iter()
-            .filter(|doc| doc.closed_at.is_none() && doc.expires_at.map_or(true, |at| at > now))
+            .filter(|doc| doc.closed_at.is_none() && doc.expires_at.is_none_or(|at| at > now))
             .cloned()
             .collect::<Vec<_>>();
 
@@ -149,7 +149,7 @@ impl Data {
 
         // Prepare a histogram of "number of RFCs completed within X months".
         let (completed_hist, completed_stats) = completion_months_histogram(&closed, |doc| {
-            (&doc.created_at, &doc.closed_at.as_ref().unwrap())
+            (&doc.created_at, doc.closed_at.as_ref().unwrap())
         });
 
         Self {
@@ -1,26 +1,62 @@
 use std::collections::HashSet;
 use std::fmt;
-use std::iter;
+use std::path::PathBuf;
+use std::sync::{Arc, LazyLock};
 use std::time::Duration;
 
-use axum::http::HeaderValue;
-use cached::proc_macro::cached;
 use chrono::{DateTime, Utc};
-use hyper::header::ACCEPT;
+use hyper::header::{ACCEPT, HeaderValue, USER_AGENT};
 use serde::{Deserialize, Deserializer, Serialize, de::DeserializeOwned};
 
-pub(super) fn build_client() -> Result<reqwest::Client, Error> {
-    Ok(reqwest::Client::builder()
-        .user_agent("ietf.rfc.observer")
-        .default_headers(
-            iter::once((ACCEPT, HeaderValue::from_static("application/json"))).collect(),
-        )
-        .build()?)
+use crate::{
+    config::Config,
+    util::{
+        cache::{Cache, Cached},
+        supervisor::Supervisor,
+        upstream,
+    },
+};
+
+/// Active and inactive groups.
+type Groups = (Vec<Group>, Vec<Group>);
+
+pub(super) static GROUPS: LazyLock<Cache<Groups, Arc<Error>>> =
+    LazyLock::new(|| Cache::new("ietf-groups", 1, Duration::from_secs(86400)));
+
+static GROUP: LazyLock<Cache<Group, Arc<Error>>> =
+    LazyLock::new(|| Cache::new("ietf-group", 1, Duration::from_secs(86400)));
+
+pub(super) static DOCUMENTS: LazyLock<Cache<Vec<super::data::Document>, Arc<Error>>> =
+    LazyLock::new(|| Cache::new("ietf-documents", 1, Duration::from_secs(600)));
+
+/// A client for the IETF Datatracker API.
+pub(super) struct Client {
+    inner: upstream::Client,
+    base_url: String,
+    /// Where we persist the data we fetch, if anywhere.
+    cache_dir: Option<PathBuf>,
+    supervisor: Supervisor,
 }
 
-async fn get<T: DeserializeOwned>(client: &reqwest::Client, path: &str) -> Result<T, Error> {
+pub(super) fn build_client(
+    client: &upstream::Client,
+    config: &Config,
+    supervisor: &Supervisor,
+) -> Client {
+    Client {
+        inner: client.named("datatracker"),
+        base_url: config.upstreams.datatracker.clone(),
+        cache_dir: config.cache.dir.clone(),
+        supervisor: supervisor.clone(),
+    }
+}
+
+async fn get<T: DeserializeOwned>(client: &Client, path: &str) -> Result<T, Error> {
     Ok(client
-        .get(format!("https://datatracker.ietf.org{path}"))
+        .inner
+        .get(format!("{}{path}", client.base_url))
+        .header(USER_AGENT, HeaderValue::from_static("ietf.rfc.observer"))
+        .header(ACCEPT, HeaderValue::from_static("application/json"))
         .send()
         .await?
         .error_for_status()?
@@ -29,7 +65,7 @@ async fn get<T: DeserializeOwned>(client: &reqwest::Client, path: &str) -> Resul
 }
 
 pub(super) async fn get_paginated<T: DeserializeOwned>(
-    client: &reqwest::Client,
+    client: &Client,
     path: &str,
 ) -> Result<Vec<T>, Error> {
     let res = get::<ListResult<T>>(client, &format!("{path}&limit=0")).await?;
@@ -47,15 +83,40 @@ pub(super) async fn get_paginated<T: DeserializeOwned>(
     Ok(objects)
 }
 
-#[cached(
-    time = 86400,
-    result = true,
-    key = "String",
-    convert = r#"{ String::from("IETF-Groups") }"#
-)]
-pub(super) async fn get_groups(
-    client: &reqwest::Client,
-) -> Result<(Vec<Group>, Vec<Group>), Error> {
+/// Returns the groups, if they have been fetched.
+pub(super) async fn get_groups(client: &Arc<Client>) -> Option<Groups> {
+    let client = client.clone();
+    GROUPS
+        .read(
+            &client.supervisor.clone(),
+            client.cache_dir.clone(),
+            client.base_url.clone(),
+            move || refresh_groups(client),
+        )
+        .await
+        .map(|groups| groups.value)
+}
+
+/// Fetches the groups, and keeps them warm from now on.
+pub(super) async fn warm_groups(client: &Arc<Client>) -> Result<Groups, Arc<Error>> {
+    GROUPS
+        .warm(client.cache_dir.clone(), client.base_url.clone(), || {
+            refresh_groups(client.clone())
+        })
+        .await
+        .map(|groups| groups.value)
+}
+
+async fn refresh_groups(client: Arc<Client>) -> Result<Groups, Arc<Error>> {
+    let res = fetch_groups(&client).await;
+    client
+        .supervisor
+        .data_sources()
+        .record("ietf.rfc.observer groups", &res);
+    res.map_err(Arc::new)
+}
+
+async fn fetch_groups(client: &Client) -> Result<Groups, Error> {
     // From a previous scan, the following group types have I-Ds or RFCs:
     // - ag
     // - area
@@ -84,31 +145,78 @@ pub(super) async fn get_groups(
     Ok((active_groups, inactive_groups))
 }
 
-#[cached(
-    time = 86400,
-    result = true,
-    key = "String",
-    convert = r#"{ String::from(acronym) }"#
-)]
-pub(super) async fn get_group(client: &reqwest::Client, acronym: &str) -> Result<Group, Error> {
-    let group_res =
-        get::<ListResult<Group>>(client, &format!("/api/v1/group/group/?acronym={acronym}"))
-            .await?;
-    assert_eq!(group_res.objects.len(), 1);
-    Ok(group_res.objects.into_iter().next().expect("present"))
-}
-
-#[cached(
-    time = 600,
-    result = true,
-    key = "String",
-    convert = r#"{ String::from(acronym) }"#
-)]
+async fn get_group(client: &Arc<Client>, acronym: &str) -> Result<Group, Arc<Error>> {
+    let (client, acronym) = (client.clone(), acronym.to_owned());
+    GROUP
+        .get(
+            &client.supervisor.clone(),
+            client.cache_dir.clone(),
+            format!("{}|{acronym}", client.base_url),
+            move || async move {
+                let group_res = get::<ListResult<Group>>(
+                    &client,
+                    &format!("/api/v1/group/group/?acronym={acronym}"),
+                )
+                .await?;
+                let mut groups = group_res.objects.into_iter();
+                match (groups.next(), groups.next()) {
+                    (Some(group), None) => Ok(group),
+                    (None, _) => Err(Arc::new(Error::UnknownGroup(acronym))),
+                    (Some(_), Some(_)) => Err(Arc::new(Error::MalformedResponse)),
+                }
+            },
+        )
+        .await
+        .map(|group| group.value)
+}
+
+/// Returns the documents for a group, if they have been fetched.
 pub(super) async fn get_documents(
-    client: &reqwest::Client,
+    client: &Arc<Client>,
+    acronym: &str,
+) -> Option<Cached<Vec<super::data::Document>>> {
+    let (client, acronym) = (client.clone(), acronym.to_owned());
+    DOCUMENTS
+        .read(
+            &client.supervisor.clone(),
+            client.cache_dir.clone(),
+            format!("{}|{acronym}", client.base_url),
+            move || refresh_documents(client, acronym),
+        )
+        .await
+}
+
+/// Fetches the documents for a group, and keeps them warm from now on.
+pub(super) async fn warm_documents(
+    client: &Arc<Client>,
+    acronym: &str,
+) -> Result<Cached<Vec<super::data::Document>>, Arc<Error>> {
+    DOCUMENTS
+        .warm(
+            client.cache_dir.clone(),
+            format!("{}|{acronym}", client.base_url),
+            || refresh_documents(client.clone(), acronym.to_owned()),
+        )
+        .await
+}
+
+async fn refresh_documents(
+    client: Arc<Client>,
+    acronym: String,
+) -> Result<Vec<super::data::Document>, Arc<Error>> {
+    let res = fetch_documents(&client, &acronym).await;
+    client
+        .supervisor
+        .data_sources()
+        .record("ietf.rfc.observer documents", &res);
+    res
+}
+
+async fn fetch_documents(
+    client: &Arc<Client>,
     acronym: &str,
-) -> Result<Vec<super::data::Document>, Error> {
-    let group = get_group(&client, acronym).await?;
+) -> Result<Vec<super::data::Document>, Arc<Error>> {
+    let group = get_group(client, acronym).await?;
 
     // Fetch the documents belonging to this group.
     let rfcs = get_paginated::<Document>(
@@ -223,17 +331,19 @@ where
     D: Deserializer<'de>,
 {
     let s = Option::<String>::deserialize(deserializer)?;
-    Ok(s.map(|s| {
+    s.map(|s| {
         DateTime::parse_from_str(&s, "%+")
             .map(|t| t.to_utc())
             .map_err(serde::de::Error::custom)
     })
-    .transpose()?)
+    .transpose()
 }
 
 #[derive(Debug)]
 pub(super) enum Error {
     MalformedResponse,
+    /// Datatracker has no group with the given acronym.
+    UnknownGroup(String),
     Request(reqwest::Error),
 }
 
@@ -241,6 +351,7 @@ impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Error::MalformedResponse => write!(f, "Response from datatracker was malformed"),
+            Error::UnknownGroup(acronym) => write!(f, "Datatracker has no group {acronym}"),
             Error::Request(e) => write!(f, "Error while processing request: {}", e),
         }
     }
@@ -3,7 +3,7 @@ use std::{collections::BTreeMap, str::FromStr, sync::OnceLock};
 use chrono::{DateTime, NaiveDate, Utc};
 use phf::phf_map;
 use regex::Regex;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use tracing::debug;
 
 use crate::rfc_observer::common::{
@@ -36,7 +36,7 @@ static RE_RFC_TEXT: OnceLock<Regex> = OnceLock::new();
 static RE_RFC_RENDERED: OnceLock<Regex> = OnceLock::new();
 static RE_RFC_TITLE: OnceLock<Regex> = OnceLock::new();
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub(super) struct TrackingIssue {
     number: i64,
     title: String,
@@ -123,7 +123,7 @@ impl FromStr for Label {
     }
 }
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub(super) struct Aggregate {
     date: NaiveDate,
     created: u64,
@@ -132,7 +132,7 @@ pub(super) struct Aggregate {
     closed: u64,
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub(super) struct Data {
     pub(super) agg: Vec<Aggregate>,
     pub(super) completed_hist: Vec<Bucket>,
@@ -294,7 +294,7 @@ impl Data {
 
         // Prepare a histogram of "number of RFCs completed within X months".
         let (completed_hist, completed_stats) = completion_months_histogram(&closed, |issue| {
-            (&issue.created_at, &issue.closed_at.as_ref().unwrap())
+            (&issue.created_at, issue.closed_at.as_ref().unwrap())
         });
 
         Self {
@@ -1,14 +1,18 @@
 use std::fmt;
 
 use crate::{
+    config::Config,
     rfc_observer::common::{IssuesWithLabelsAndBodyQuery, issues_with_labels_and_body_query},
-    util::github,
+    util::{github, upstream},
 };
 
 use super::data::TrackingIssue;
 
-pub(super) async fn get_tracking_issues() -> Result<Vec<TrackingIssue>, Error> {
-    let client = github::Client::new("rust.rfc.observer")?;
+pub(super) async fn get_tracking_issues(
+    client: &upstream::Client,
+    config: &Config,
+) -> Result<Vec<TrackingIssue>, Error> {
+    let client = github::Client::new(client, "rust.rfc.observer", config)?;
 
     let data = client
         .post_paginated_graphql::<IssuesWithLabelsAndBodyQuery>(
@@ -30,9 +34,8 @@ pub(super) async fn get_tracking_issues() -> Result<Vec<TrackingIssue>, Error> {
         .edges
         .into_iter()
         .flat_map(|issues| issues.into_iter())
-        .map(|e| e.and_then(|edge| edge.node))
-        .flatten()
-        .flat_map(|issue| TrackingIssue::new(issue))
+        .filter_map(|e| e.and_then(|edge| edge.node))
+        .flat_map(TrackingIssue::new)
         .collect::<Vec<_>>();
 
     tracking_issues.sort_by_key(|issue| (issue.rfc, issue.created_at, issue.closed_at));
@@ -1,3 +1,4 @@
+use std::sync::{Arc, LazyLock};
 use std::time::Duration;
 
 use askama::Template;
@@ -8,38 +9,84 @@ use atrium_api::{
     com::atproto::repo::list_records,
     types::{BlobRef, TryFromUnknown, TypedBlobRef, Union},
 };
-use atrium_xrpc_client::reqwest::ReqwestClientBuilder;
-use axum::{Router, routing::get};
-use cached::proc_macro::cached;
+
+use axum::{Extension, Router, routing::get};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    util::{
+        cache::Cache,
+        error::{ErrorPages, Problem},
+        http_cache::CachePolicy,
+        supervisor::Supervisor,
+        upstream,
+    },
+};
+
+/// The posts in the feed, with their URIs.
+type Feed = Vec<(String, Post)>;
+
+static FEED: LazyLock<Cache<Feed, Arc<anyhow::Error>>> =
+    LazyLock::new(|| Cache::new("siso.dev-feed", 1, Duration::from_secs(60)));
 
 pub(crate) fn build() -> Router {
-    Router::new().route("/", get(index))
+    let router = Router::new().route("/", get(index).layer(CachePolicy::public(FEED.ttl())));
+    ErrorPages::new(|error| ErrorPage { error }).apply(router)
+}
+
+#[derive(Template)]
+#[template(path = "siso.dev/error.html")]
+struct ErrorPage {
+    error: Problem,
 }
 
-#[derive(Clone, Template, WebTemplate)]
+#[derive(Template, WebTemplate)]
 #[template(path = "siso.dev/index.html")]
 struct Index {
-    feed: Vec<(String, Post)>,
+    feed: Feed,
+    /// Base URL for images in the feed.
+    cdn: String,
 }
 
-#[cached(time = 60)]
-async fn index() -> Index {
-    let feed = match get_feed().await {
-        Ok(feed) => feed,
+async fn index(
+    Extension(client): Extension<upstream::Client>,
+    Extension(config): Extension<Arc<Config>>,
+    Extension(supervisor): Extension<Supervisor>,
+) -> Index {
+    let cdn = config.upstreams.bluesky_cdn.clone();
+    let (dir, key) = (
+        config.cache.dir.clone(),
+        config.upstreams.bluesky_pds.clone(),
+    );
+    let res = FEED
+        .get(&supervisor.clone(), dir, key, move || {
+            fetch_feed(client, config, supervisor)
+        })
+        .await;
+    let feed = match res {
+        Ok(feed) => feed.value,
         Err(e) => {
             tracing::error!("Failed to get feed: {:?}", e);
             vec![]
         }
     };
-    Index { feed }
+
+    Index { feed, cdn }
 }
 
-async fn get_feed() -> anyhow::Result<Vec<(String, Post)>> {
-    let client = AtpServiceClient::new(
-        ReqwestClientBuilder::new("https://bsky.social")
-            .client(reqwest::ClientBuilder::new().use_rustls_tls().build()?)
-            .build(),
-    );
+async fn fetch_feed(
+    client: upstream::Client,
+    config: Arc<Config>,
+    supervisor: Supervisor,
+) -> Result<Feed, Arc<anyhow::Error>> {
+    let res = get_feed(&client, &config.upstreams.bluesky_pds).await;
+    supervisor.data_sources().record("siso.dev feed", &res);
+    res.map_err(Arc::new)
+}
+
+async fn get_feed(client: &upstream::Client, pds: &str) -> anyhow::Result<Feed> {
+    let client = AtpServiceClient::new(client.named("bluesky_pds").xrpc(pds));
 
     let feed = client
         .service
@@ -71,7 +118,7 @@ async fn get_feed() -> anyhow::Result<Vec<(String, Post)>> {
         .collect())
 }
 
-#[derive(Clone)]
+#[derive(Clone, Deserialize, Serialize)]
 pub(super) struct Post(app::bsky::feed::post::Record);
 
 impl Post {
@@ -86,7 +133,10 @@ impl Post {
         )
     }
 
-    pub(super) fn images(&self) -> impl Iterator<Item = (String, &String)> + '_ {
+    pub(super) fn images<'a>(
+        &'a self,
+        cdn: &'a str,
+    ) -> impl Iterator<Item = (String, &'a String)> + 'a {
         self.0
             .embed
             .iter()
@@ -98,7 +148,7 @@ impl Post {
                 }
             })
             .flatten()
-            .map(|i| {
+            .map(move |i| {
                 let link = match &i.image {
                     BlobRef::Typed(TypedBlobRef::Blob(blob_ref)) => &blob_ref.r#ref.0.to_string(),
                     BlobRef::Untyped(blob_ref) => &blob_ref.cid,
@@ -106,7 +156,7 @@ impl Post {
 
                 (
                     format!(
-                        "https://cdn.bsky.app/img/feed_thumbnail/plain/{}/{}@jpeg",
+                        "{cdn}/img/feed_thumbnail/plain/{}/{}@jpeg",
                         "did:plc:mzwculbn44rdeouyzjp4y6gx", link,
                     ),
                     &i.alt,
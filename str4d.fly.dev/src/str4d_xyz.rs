@@ -4,18 +4,28 @@ use askama::Template;
 use askama_web::WebTemplate;
 use axum::{Router, extract::Query, response::Redirect, routing::get};
 
-use crate::util::get_temp_redir;
+use crate::util::{
+    RedirectKind,
+    error::{ErrorPages, Problem},
+    get_redir,
+};
 
 pub(crate) fn build() -> Router {
-    Router::new()
+    let router = Router::new()
         .route("/", get(index))
-        .nest("/blog", blog())
         .nest("/rage", github_project_with_clone("str4d/rage"))
         .nest("/wage", github_project("str4d/wage"))
         .nest(
             "/age-plugin-yubikey",
             github_project("str4d/age-plugin-yubikey"),
-        )
+        );
+    ErrorPages::new(|error| ErrorPage { error }).apply(router)
+}
+
+#[derive(Template)]
+#[template(path = "str4d.xyz/error.html")]
+struct ErrorPage {
+    error: Problem,
 }
 
 #[derive(Template, WebTemplate)]
@@ -26,52 +36,21 @@ async fn index() -> Index {
     Index {}
 }
 
-fn blog() -> Router {
-    Router::new()
-        .route("/", get_temp_redir("https://words.str4d.xyz"))
-        .route(
-            "/posts/first-post/",
-            get_temp_redir("https://words.str4d.xyz/first-post/"),
-        )
-        .route(
-            "/posts/ignore-request-urls-in-jetty/",
-            get_temp_redir("https://words.str4d.xyz/ignore-request-urls-in-jetty/"),
-        )
-        .route(
-            "/posts/passing-custom-options-through-I2CP/",
-            get_temp_redir("https://words.str4d.xyz/passing-custom-options-through-I2CP/"),
-        )
-        .route(
-            "/posts/i2p-android-dev-builds/",
-            get_temp_redir("https://words.str4d.xyz/i2p-android-dev-builds/"),
-        )
-        .route(
-            "/posts/i2p-android-dev-the-second/",
-            get_temp_redir("https://words.str4d.xyz/i2p-android-dev-the-second/"),
-        )
-        .route(
-            "/posts/i2p-android-dev-the-third/",
-            get_temp_redir("https://words.str4d.xyz/i2p-android-dev-the-third/"),
-        )
-        .route(
-            "/posts/i2p-android-dev-the-fourth/",
-            get_temp_redir("https://words.str4d.xyz/i2p-android-dev-the-fourth/"),
-        )
-        .route(
-            "/posts/gpg-key-transition/",
-            get_temp_redir("https://words.str4d.xyz/gpg-key-transition/"),
-        )
-}
-
 fn github_project(project: &str) -> Router {
     Router::new()
         .route(
             "/",
-            get_temp_redir(&format!("https://github.com/{}", project)),
+            get_redir(
+                RedirectKind::Temporary,
+                &format!("https://github.com/{}", project),
+            ),
         )
         .route(
             "/report",
-            get_temp_redir(&format!("https://github.com/{}/issues/new/choose", project)),
+            get_redir(
+                RedirectKind::Temporary,
+                &format!("https://github.com/{}/issues/new/choose", project),
+            ),
         )
 }
 
@@ -0,0 +1,416 @@
+//! End-to-end tests.
+//!
+//! Each test builds the full [`Multiplexer`] (as served in production), with every
+//! upstream pointed at a local [`MockUpstream`] that serves recorded responses from
+//! `tests/fixtures`. Upstreams are mounted under a path prefix on the mock server:
+//!
+//! | Upstream          | Prefix            |
+//! |-------------------|-------------------|
+//! | `bluesky_pds`     | `/pds`            |
+//! | `bluesky_appview` | `/appview`        |
+//! | `bluesky_cdn`     | `/cdn`            |
+//! | `datatracker`     | `/datatracker`    |
+//! | `eprint_authors`  | `/authors`        |
+//! | `feeds_mod`       | `/feeds-mod`      |
+//! | `firehose_counts` | `/firehose`       |
+//! | `github_graphql`  | `/graphql`        |
+//! | `relay`           | `/relay/{host}`   |
+//!
+//! A test only mocks the routes it needs; requests for anything else get a 404, which
+//! is how we exercise upstream failures.
+//!
+//! Cached upstream data is keyed by the configured upstreams, and every mock server
+//! listens on its own port, so tests don't see each other's cached responses.
+
+use std::{
+    fs,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use axum::{
+    Json, Router,
+    body::Body,
+    extract::{ConnectInfo, Request},
+    http::{
+        HeaderMap, HeaderName, HeaderValue, StatusCode,
+        header::{AUTHORIZATION, CONTENT_TYPE, HOST},
+    },
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{MethodRouter, post},
+};
+use serde_json::Value;
+use tokio::net::TcpListener;
+use tower::ServiceExt;
+
+use crate::{
+    config::{Bluesky, Config, GitHub, Upstreams},
+    util::{
+        Multiplexer, client_ip::TrustedProxies, shutdown::Shutdown, supervisor::Supervisor,
+        upstream,
+    },
+};
+
+mod assets;
+mod atp_fyi;
+mod cache;
+mod client_ip;
+mod compression;
+mod cryptography_social;
+mod error_pages;
+mod hosts;
+mod http_cache;
+mod limits;
+mod metrics;
+mod outbound;
+mod recording;
+mod rfc_observer;
+mod scheduler;
+mod security;
+mod single_flight;
+mod siso_dev;
+mod supervisor;
+mod traces;
+
+/// The secret configured for every upstream that needs one.
+const SECRET: &str = "test";
+
+/// Returns the contents of the given file in `tests/fixtures`.
+fn fixture(name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name);
+    fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("Failed to read fixture {}: {e}", path.display()))
+}
+
+/// Responds with the given JSON fixture.
+fn json(name: &str) -> Response {
+    ([(CONTENT_TYPE, "application/json")], fixture(name)).into_response()
+}
+
+/// An HTTP server that stands in for our upstreams.
+struct MockUpstream {
+    router: Router,
+}
+
+impl MockUpstream {
+    fn new() -> Self {
+        Self {
+            router: Router::new(),
+        }
+    }
+
+    fn route(mut self, path: &str, method_router: MethodRouter) -> Self {
+        self.router = self.router.route(path, method_router);
+        self
+    }
+
+    /// Counts the requests made to this server in `requests`.
+    fn count_requests(mut self, requests: Arc<AtomicUsize>) -> Self {
+        self.router = self
+            .router
+            .layer(middleware::from_fn(move |req: Request, next: Next| {
+                requests.fetch_add(1, Ordering::SeqCst);
+                next.run(req)
+            }));
+        self
+    }
+
+    /// Starts serving on a random local port, and returns the server's base URL.
+    async fn start(self) -> String {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .await
+            .expect("can bind");
+        let addr = listener.local_addr().expect("bound");
+        tokio::spawn(async move { axum::serve(listener, self.router).await });
+        format!("http://{addr}")
+    }
+}
+
+/// Serves GitHub GraphQL API responses from `github/{operation}-{cursor}.json`, where
+/// `cursor` is the `after` variable of the query (`page-1` for the first page).
+fn github_graphql() -> MethodRouter {
+    post(|headers: HeaderMap, Json(query): Json<Value>| async move {
+        if headers
+            .get(AUTHORIZATION)
+            .is_none_or(|auth| auth != format!("Bearer {SECRET}").as_str())
+        {
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+
+        let operation = query["operationName"].as_str().expect("present");
+        let cursor = query["variables"]["after"].as_str().unwrap_or("page-1");
+        json(&format!("github/{operation}-{cursor}.json"))
+    })
+}
+
+/// An instance of our server, configured to use the upstreams at `base_url`.
+struct TestApp {
+    app: Multiplexer<()>,
+    config: Arc<Config>,
+    client: upstream::Client,
+    supervisor: Supervisor,
+    shutdown: Shutdown,
+}
+
+impl TestApp {
+    fn new(base_url: &str) -> Self {
+        Self::with_config(base_url, |_| ())
+    }
+
+    /// Like [`TestApp::new`], but lets the test adjust the configuration.
+    fn with_config(base_url: &str, configure: impl FnOnce(&mut Config)) -> Self {
+        let mut config = Config {
+            hosts_file: Path::new(env!("CARGO_MANIFEST_DIR")).join("hosts.toml"),
+            background_services: false,
+            github: GitHub {
+                api_key: Some(SECRET.parse().expect("infallible")),
+            },
+            bluesky: Bluesky {
+                app_password: Some(SECRET.parse().expect("infallible")),
+                ..Bluesky::default()
+            },
+            upstreams: Upstreams {
+                bluesky_pds: format!("{base_url}/pds"),
+                bluesky_appview: format!("{base_url}/appview"),
+                bluesky_cdn: format!("{base_url}/cdn"),
+                datatracker: format!("{base_url}/datatracker"),
+                eprint_authors: format!("{base_url}/authors"),
+                feeds_mod: format!("{base_url}/feeds-mod"),
+                firehose_counts: format!("{base_url}/firehose"),
+                github_graphql: format!("{base_url}/graphql"),
+                relay: format!("{base_url}/relay/{{host}}"),
+            },
+            ..Config::default()
+        };
+        configure(&mut config);
+        let config = Arc::new(config);
+        let client = upstream::Client::new(&config.recording, &config.outbound)
+            .expect("valid client configuration");
+        let shutdown = Shutdown::new();
+        let supervisor = Supervisor::new(shutdown.clone());
+
+        let app = crate::build_app(config.clone(), client.clone(), supervisor.clone())
+            .expect("hosts.toml is valid");
+
+        Self {
+            app,
+            config,
+            client,
+            supervisor,
+            shutdown,
+        }
+    }
+
+    /// Requests `path` from `host`, returning the response status and body.
+    async fn get(&self, host: &str, path: &str) -> (StatusCode, String) {
+        let req = Request::builder()
+            .uri(path)
+            .header(HOST, host)
+            .body(Body::empty())
+            .expect("valid");
+        let res = self.app.clone().oneshot(req).await.expect("infallible");
+
+        let status = res.status();
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .expect("can read body");
+        (status, String::from_utf8(body.to_vec()).expect("UTF-8"))
+    }
+
+    /// Requests `path` from `host`, parsing the response body as JSON.
+    async fn get_json(&self, host: &str, path: &str) -> (StatusCode, Value) {
+        let (status, body) = self.get(host, path).await;
+        let json = serde_json::from_str(&body)
+            .unwrap_or_else(|e| panic!("Response is not JSON ({e}): {body}"));
+        (status, json)
+    }
+
+    /// Requests `path` from `host` with the given headers.
+    async fn get_with(
+        &self,
+        host: &str,
+        path: &str,
+        headers: &[(HeaderName, &HeaderValue)],
+    ) -> Response {
+        let mut req = Request::builder().uri(path).header(HOST, host);
+        for (name, value) in headers {
+            req = req.header(name, *value);
+        }
+        let req = req.body(Body::empty()).expect("valid");
+        self.app.clone().oneshot(req).await.expect("infallible")
+    }
+
+    /// Requests `path` from `host` via fly.io's proxy (at `proxy`), on behalf of the
+    /// client at `client`.
+    async fn get_via_proxy(
+        &self,
+        proxy: IpAddr,
+        client: Ipv4Addr,
+        host: &str,
+        path: &str,
+    ) -> Response {
+        let mut req = Request::builder()
+            .uri(path)
+            .header(HOST, host)
+            .header("fly-client-ip", client.to_string())
+            .body(Body::empty())
+            .expect("valid");
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from((proxy, 443))));
+        self.app.clone().oneshot(req).await.expect("infallible")
+    }
+
+    /// Waits until the data served at `path` on `host` has been fetched in the
+    /// background (see [`crate::util::cache::Cache::read`]).
+    async fn wait_for_data(&self, host: &str, path: &str) {
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while self.get_with(host, path, &[]).await.status() == StatusCode::SERVICE_UNAVAILABLE {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("data should be fetched");
+    }
+
+    /// Stops any background services.
+    async fn stop(self) {
+        self.shutdown.drain(Duration::from_secs(1)).await;
+    }
+}
+
+/// The proxies that `fly.toml` configures us to trust.
+fn fly_trusted_proxies() -> TrustedProxies {
+    let fly: toml::Table = toml::from_str(include_str!("../fly.toml")).expect("valid TOML");
+    fly["env"]["TRUSTED_PROXIES"]
+        .as_str()
+        .expect("TRUSTED_PROXIES is set")
+        .parse()
+        .expect("valid")
+}
+
+/// A temporary directory that is removed when dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        Self(std::env::temp_dir().join(format!("str4d-fly-dev-{}-{name}", std::process::id())))
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Waits (for a little while) until `f` returns true.
+async fn wait_until(mut f: impl FnMut() -> bool) {
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while !f() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("condition should become true");
+}
+
+#[tokio::test]
+async fn health() {
+    let app = TestApp::new(&MockUpstream::new().start().await);
+
+    for host in ["atp.fyi", "unknown.example.com"] {
+        let (status, body) = app.get_json(host, "/_health").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "ok");
+    }
+}
+
+#[tokio::test]
+async fn ready_without_optional_secrets() {
+    let app = TestApp::with_config(&MockUpstream::new().start().await, |config| {
+        config.github.api_key = None;
+        config.bluesky.app_password = None;
+    });
+
+    let (status, ready) = app.get_json("atp.fyi", "/_ready").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(ready["secrets"]["GITHUB_API_KEY"], false);
+    assert_eq!(ready["secrets"]["BLUESKY_APP_PASSWORD"], false);
+}
+
+#[tokio::test]
+async fn ready_reports_failing_services() {
+    let app = TestApp::new(&MockUpstream::new().start().await);
+
+    app.supervisor.supervise("failing service", |_| async {
+        anyhow::bail!("upstream is down")
+    });
+    wait_until(|| {
+        app.supervisor
+            .status("failing service")
+            .is_some_and(|status| status.last_error.is_some())
+    })
+    .await;
+
+    // `/_ready` is only informational, so still succeeds.
+    let (status, ready) = app.get_json("atp.fyi", "/_ready").await;
+    assert_eq!(status, StatusCode::OK);
+    let service = &ready["services"]["failing service"];
+    assert_eq!(service["state"], "backoff");
+    assert_eq!(service["last_error"]["message"], "upstream is down");
+    // Liveness isn't affected.
+    let (status, _) = app.get_json("atp.fyi", "/_health").await;
+    assert_eq!(status, StatusCode::OK);
+
+    app.stop().await;
+}
+
+#[tokio::test]
+async fn ready_reports_data_sources() {
+    let upstream = MockUpstream::new()
+        .route("/graphql", github_graphql())
+        .start()
+        .await;
+    let app = TestApp::new(&upstream);
+
+    app.wait_for_data("go.rfc.observer", "/api/data").await;
+    let (_, ready) = app.get_json("go.rfc.observer", "/_ready").await;
+    let source = &ready["data_sources"]["go.rfc.observer proposals"];
+    assert!(source["last_success"].is_string());
+    assert!(source["last_error"].is_null());
+
+    // Each instance of the server tracks its own data sources.
+    let other = TestApp::new(&upstream);
+    let (_, ready) = other.get_json("go.rfc.observer", "/_ready").await;
+    assert_eq!(ready["data_sources"], serde_json::json!({}));
+}
+
+#[tokio::test]
+async fn aliases_redirect() {
+    let app = TestApp::new(&MockUpstream::new().start().await);
+
+    let req = Request::builder()
+        .uri("/")
+        .header(HOST, "www.siso.dev")
+        .body(Body::empty())
+        .expect("valid");
+    let res = app.app.clone().oneshot(req).await.expect("infallible");
+    assert!(res.status().is_redirection());
+    assert_eq!(res.headers()["location"], "https://siso.dev/");
+}
+
+#[tokio::test]
+async fn unknown_host() {
+    let app = TestApp::new(&MockUpstream::new().start().await);
+
+    let (status, _) = app.get("unknown.example.com", "/").await;
+    assert_eq!(status, StatusCode::MISDIRECTED_REQUEST);
+}
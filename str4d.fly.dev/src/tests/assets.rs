@@ -0,0 +1,84 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use axum::http::{
+    StatusCode,
+    header::{CACHE_CONTROL, CONTENT_TYPE, X_CONTENT_TYPE_OPTIONS},
+};
+use sha2::{Digest, Sha256};
+
+use super::{MockUpstream, TestApp};
+use crate::util::assets::ASSETS;
+
+/// Returns the URLs of the scripts that `page` loads from `/static/`.
+fn static_scripts(page: &str) -> Vec<&str> {
+    page.split("<script src=\"")
+        .skip(1)
+        .filter_map(|rest| rest.split_once('"').map(|(src, _)| src))
+        .inspect(|src| assert!(src.starts_with("/static/"), "{src} is not self-hosted"))
+        .collect()
+}
+
+#[tokio::test]
+async fn pages_use_self_hosted_assets() {
+    let app = TestApp::new(&MockUpstream::new().start().await);
+
+    for (host, path, scripts) in [
+        ("go.rfc.observer", "/", 3),
+        ("rust.rfc.observer", "/", 3),
+        ("atp.fyi", "/network", 3),
+    ] {
+        let (status, page) = app.get(host, path).await;
+        assert_eq!(status, StatusCode::OK);
+        let srcs = static_scripts(&page);
+        assert_eq!(srcs.len(), scripts);
+
+        for src in srcs {
+            let res = app.get_with(host, src, &[]).await;
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(res.headers()[CONTENT_TYPE], "text/javascript");
+            assert_eq!(
+                res.headers()[CACHE_CONTROL],
+                "public, max-age=31536000, immutable",
+            );
+            assert_eq!(res.headers()[X_CONTENT_TYPE_OPTIONS], "nosniff");
+        }
+    }
+}
+
+#[tokio::test]
+async fn assets_are_content_hashed() {
+    let app = TestApp::new(&MockUpstream::new().start().await);
+
+    let url = crate::util::assets::url("chart.umd.js");
+    let (status, _) = app.get("s-s.sh", &url).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Only the current version of an asset is served.
+    for outdated in [
+        "/static/chart.umd.js",
+        "/static/chart.umd.0000000000000000.js",
+    ] {
+        let (status, _) = app.get("s-s.sh", outdated).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
+
+#[test]
+fn embedded_assets_match_pinned_hashes() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("res/static/SHA256SUMS");
+    let sums = fs::read_to_string(&path).expect("SHA256SUMS exists");
+    let pinned = sums
+        .lines()
+        .map(|line| line.split_once("  ").expect("valid line"))
+        .map(|(hash, name)| (name, hash))
+        .collect::<HashMap<_, _>>();
+
+    assert_eq!(pinned.len(), ASSETS.len());
+    for &(name, data) in ASSETS {
+        assert_eq!(
+            Some(&format!("{:x}", Sha256::digest(data)).as_str()),
+            pinned.get(name),
+            "{name} doesn't match SHA256SUMS",
+        );
+    }
+}
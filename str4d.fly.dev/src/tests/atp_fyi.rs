@@ -0,0 +1,222 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{MethodRouter, get, post},
+};
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::{MockUpstream, TestApp, github_graphql, json, wait_until};
+use crate::atp_fyi::{self, network::firehose};
+
+#[derive(Deserialize)]
+struct Cursor {
+    cursor: Option<String>,
+}
+
+/// Mocks everything that the network map depends on, with the given handler for
+/// signing in to Bluesky.
+fn network_upstreams(create_session: MethodRouter) -> MockUpstream {
+    MockUpstream::new()
+        .route("/pds/xrpc/com.atproto.server.createSession", create_session)
+        .route(
+            "/pds/xrpc/app.bsky.labeler.getServices",
+            get(|| async { json("bluesky/getServices.json") }),
+        )
+        .route(
+            "/pds/xrpc/app.bsky.feed.getSuggestedFeeds",
+            get(|Query(q): Query<Cursor>| async move {
+                let cursor = q.cursor.as_deref().unwrap_or("page-1");
+                json(&format!("bluesky/getSuggestedFeeds-{cursor}.json"))
+            }),
+        )
+        .route(
+            "/feeds-mod/xrpc/blue.feeds.mod.getLabellers",
+            get(|| async { json("bluesky/getLabellers.json") }),
+        )
+        .route(
+            "/relay/{host}/xrpc/com.atproto.sync.listHosts",
+            get(
+                |Path(host): Path<String>, Query(q): Query<Cursor>| async move {
+                    // One relay being down shouldn't prevent us from drawing the map.
+                    if host == "relay.feeds.blue" {
+                        return StatusCode::BAD_GATEWAY.into_response();
+                    }
+                    let cursor = q.cursor.as_deref().unwrap_or("page-1");
+                    json(&format!("bluesky/listHosts-{cursor}.json"))
+                },
+            ),
+        )
+}
+
+fn node_labels(map: &Value) -> Vec<&str> {
+    map["nodes"]
+        .as_array()
+        .expect("nodes")
+        .iter()
+        .map(|node| node["label"].as_str().expect("label"))
+        .collect()
+}
+
+#[tokio::test]
+async fn roadmap() {
+    let upstream = MockUpstream::new()
+        .route("/graphql", github_graphql())
+        .start()
+        .await;
+    let app = TestApp::new(&upstream);
+    app.wait_for_data("atp.fyi", "/roadmap").await;
+
+    let (status, body) = app.get("atp.fyi", "/roadmap").await;
+    assert_eq!(status, StatusCode::OK);
+    // Issues from both pages are present.
+    for title in [
+        "Edit button for posts",
+        "Dark mode schedule",
+        "Group chats",
+        "Custom emoji",
+    ] {
+        assert!(body.contains(title), "missing {title}");
+    }
+    assert!(body.contains("https://github.com/bluesky-social/social-app/issues/103"));
+}
+
+#[tokio::test]
+async fn roadmap_is_warmed() {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let upstream = MockUpstream::new()
+        .route("/graphql", github_graphql())
+        .count_requests(requests.clone())
+        .start()
+        .await;
+    let app = TestApp::new(&upstream);
+
+    atp_fyi::warm_roadmap(&app.client, &app.config, &app.supervisor)
+        .run_once()
+        .await
+        .expect("upstream is up");
+    let warmed = requests.load(Ordering::SeqCst);
+    assert!(warmed > 0);
+
+    // The warmed roadmap is served without any more upstream requests.
+    let (status, body) = app.get("atp.fyi", "/roadmap").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("Edit button for posts"));
+    assert_eq!(requests.load(Ordering::SeqCst), warmed);
+}
+
+#[tokio::test]
+async fn roadmap_upstream_failure() {
+    let app = TestApp::new(&MockUpstream::new().start().await);
+
+    let (status, body) = app.get("atp.fyi", "/roadmap").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert!(body.contains("We are still fetching this data."));
+}
+
+#[tokio::test]
+async fn network_map() {
+    let upstream = network_upstreams(post(|| async { json("bluesky/createSession.json") }))
+        .start()
+        .await;
+    let app = TestApp::new(&upstream);
+    app.wait_for_data("atp.fyi", "/api/network-map").await;
+
+    let (status, map) = app.get_json("atp.fyi", "/api/network-map").await;
+    assert_eq!(status, StatusCode::OK);
+    let labels = node_labels(&map);
+
+    // PDSs are collected across pages of every relay.
+    assert!(labels.contains(&"pds.example.com (10 accounts)"));
+    assert!(labels.contains(&"pds.example.org (3 accounts)"));
+    assert!(labels.contains(&"morel.us-east.host.bsky.network (5000 accounts)"));
+
+    // Labelers without labels are ignored.
+    assert!(labels.contains(&"Mock Labeler"));
+    assert!(!labels.contains(&"Empty Labeler"));
+
+    // Feeds are collected across pages.
+    assert!(labels.contains(&"Mock Feed"));
+    assert!(labels.contains(&"Another Mock Feed"));
+}
+
+#[tokio::test]
+async fn network_map_sign_in_failure() {
+    let upstream = network_upstreams(post(|| async { StatusCode::UNAUTHORIZED }))
+        .start()
+        .await;
+    let app = TestApp::new(&upstream);
+
+    let (status, _) = app.get_json("atp.fyi", "/api/network-map").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[tokio::test]
+async fn firehose_rates() {
+    let upstream = MockUpstream::new()
+        .route(
+            "/firehose",
+            get(|| async { super::fixture("firehose/metrics.txt") }),
+        )
+        .start()
+        .await;
+    let app = TestApp::new(&upstream);
+
+    let (client, config) = (app.client.clone(), app.config.clone());
+    app.supervisor.supervise(firehose::SERVICE, move |service| {
+        firehose::monitor(client.clone(), config.clone(), service)
+    });
+    wait_until(|| {
+        app.supervisor
+            .status(firehose::SERVICE)
+            .is_some_and(|status| status.last_success.is_some())
+    })
+    .await;
+
+    let (status, body) = app.get("atp.fyi", "/").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("Firehose rates"));
+    assert!(body.contains("Total:"));
+
+    let (status, ready) = app.get_json("atp.fyi", "/_ready").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(ready["services"][firehose::SERVICE]["state"], "running");
+
+    app.stop().await;
+}
+
+#[tokio::test]
+async fn firehose_upstream_failure() {
+    let app = TestApp::new(&MockUpstream::new().start().await);
+
+    let (client, config) = (app.client.clone(), app.config.clone());
+    app.supervisor.supervise(firehose::SERVICE, move |service| {
+        firehose::monitor(client.clone(), config.clone(), service)
+    });
+    wait_until(|| {
+        app.supervisor
+            .status(firehose::SERVICE)
+            .is_some_and(|status| status.last_error.is_some())
+    })
+    .await;
+
+    // The rest of atp.fyi (and our other sites) still work.
+    let (status, ready) = app.get_json("atp.fyi", "/_ready").await;
+    assert_eq!(status, StatusCode::OK);
+    let service = &ready["services"][firehose::SERVICE];
+    assert_eq!(service["state"], "backoff");
+    assert!(
+        service["last_error"]["message"]
+            .as_str()
+            .expect("present")
+            .contains("Failed to fetch firehose metrics")
+    );
+
+    app.stop().await;
+}
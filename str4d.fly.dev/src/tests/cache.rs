@@ -0,0 +1,308 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+use std::time::Duration;
+
+use axum::{
+    http::{StatusCode, header::RETRY_AFTER},
+    response::IntoResponse,
+};
+use serde_json::Value;
+
+use super::{TempDir, wait_until};
+use crate::util::{
+    cache::{AgedJson, Cache, Cached, DATA_AGE},
+    shutdown::Shutdown,
+    supervisor::Supervisor,
+};
+
+const TTL: Duration = Duration::from_secs(60);
+
+/// Returns a cache that lives for the rest of the test run, as the caches for our data
+/// sources do.
+fn cache(version: u32, ttl: Duration) -> &'static Cache<Vec<String>, &'static str> {
+    Box::leak(Box::new(Cache::new("test", version, ttl)))
+}
+
+fn supervisor() -> Supervisor {
+    Supervisor::new(Shutdown::new())
+}
+
+async fn get_result(
+    cache: &'static Cache<Vec<String>, &'static str>,
+    dir: &TempDir,
+    fetched: Result<&str, &'static str>,
+) -> Result<Cached<Vec<String>>, &'static str> {
+    let fetched = fetched.map(|value| vec![value.to_owned()]);
+    cache
+        .get(&supervisor(), Some(dir.0.clone()), "key".into(), || async {
+            fetched
+        })
+        .await
+}
+
+async fn get(
+    cache: &'static Cache<Vec<String>, &'static str>,
+    dir: &TempDir,
+    fetched: &str,
+) -> Vec<String> {
+    get_result(cache, dir, Ok(fetched))
+        .await
+        .expect("fetch succeeds")
+        .value
+}
+
+#[tokio::test]
+async fn persisted_across_restarts() {
+    let dir = TempDir::new("cache-restarts");
+
+    let cache = cache(1, TTL);
+    assert_eq!(get(cache, &dir, "first").await, ["first"]);
+    // Cached in memory.
+    assert_eq!(get(cache, &dir, "second").await, ["first"]);
+
+    // A new cache (as after a restart) loads the persisted value.
+    let restarted = self::cache(1, TTL);
+    assert_eq!(get(restarted, &dir, "second").await, ["first"]);
+}
+
+#[tokio::test]
+async fn stale_values_are_refreshed_in_background() {
+    let dir = TempDir::new("cache-stale");
+
+    // Values are immediately stale.
+    let cache = cache(1, Duration::ZERO);
+    assert_eq!(get(cache, &dir, "first").await, ["first"]);
+
+    // The stale value is served while it is refreshed.
+    assert_eq!(get(cache, &dir, "second").await, ["first"]);
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while get(cache, &dir, "third").await == ["first"] {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("value is refreshed");
+
+    // Values loaded from disk are refreshed in the same way.
+    let restarted = self::cache(1, Duration::ZERO);
+    assert_ne!(get(restarted, &dir, "fourth").await, ["fourth"]);
+}
+
+#[tokio::test]
+async fn reads_fetch_missing_values_in_background() {
+    let dir = TempDir::new("cache-reads");
+
+    let cache = cache(1, TTL);
+    let supervisor = supervisor();
+    let read = || {
+        cache.read(&supervisor, Some(dir.0.clone()), "key".into(), || async {
+            Ok(vec!["first".to_owned()])
+        })
+    };
+
+    // Reads don't wait for the value to be fetched.
+    assert!(read().await.is_none());
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while read().await.is_none() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("value is fetched");
+    assert_eq!(get(cache, &dir, "second").await, ["first"]);
+}
+
+#[tokio::test]
+async fn warmed_values_are_not_refreshed_by_reads() {
+    let dir = TempDir::new("cache-warmed");
+
+    // Values are immediately stale, but are kept warm.
+    let cache = cache(1, Duration::ZERO);
+    let warmed = cache
+        .warm(Some(dir.0.clone()), "key".into(), || async {
+            Ok(vec!["first".to_owned()])
+        })
+        .await;
+    assert_eq!(warmed.expect("fetch succeeds").value, ["first"]);
+
+    for _ in 0..3 {
+        assert_eq!(get(cache, &dir, "second").await, ["first"]);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test]
+async fn failures_do_not_replace_values() {
+    let dir = TempDir::new("cache-failures");
+
+    let cache = cache(1, Duration::ZERO);
+    let res = get_result(cache, &dir, Err("unavailable")).await;
+    assert_eq!(res.err(), Some("unavailable"));
+
+    assert_eq!(get(cache, &dir, "first").await, ["first"]);
+    for _ in 0..3 {
+        let res = get_result(cache, &dir, Err("unavailable")).await;
+        assert_eq!(res.expect("stale value").value, ["first"]);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+/// Fetches `key` from `cache` ten times concurrently, returning the results and how many
+/// times `fetched` was actually fetched.
+async fn get_concurrently(
+    cache: &'static Cache<Vec<String>, &'static str>,
+    fetched: Result<&'static str, &'static str>,
+) -> (Vec<Result<Vec<String>, &'static str>>, usize) {
+    let fetches = Arc::new(AtomicUsize::new(0));
+    let handles = (0..10)
+        .map(|_| {
+            let fetches = fetches.clone();
+            let supervisor = supervisor();
+            tokio::spawn(async move {
+                cache
+                    .get(&supervisor, None, "key".into(), move || async move {
+                        fetches.fetch_add(1, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        fetched.map(|value| vec![value.to_owned()])
+                    })
+                    .await
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut results = vec![];
+    for handle in handles {
+        results.push(handle.await.expect("no panic").map(|cached| cached.value));
+    }
+    (results, fetches.load(Ordering::SeqCst))
+}
+
+#[tokio::test]
+async fn concurrent_fetches_are_coalesced() {
+    let (results, fetches) = get_concurrently(cache(1, TTL), Ok("value")).await;
+    assert_eq!(fetches, 1);
+    assert!(results.iter().all(|res| res == &Ok(vec!["value".into()])));
+}
+
+#[tokio::test]
+async fn concurrent_failures_are_shared() {
+    let cache = cache(1, TTL);
+    let (results, fetches) = get_concurrently(cache, Err("unavailable")).await;
+    assert_eq!(fetches, 1);
+    assert!(results.iter().all(|res| res == &Err("unavailable")));
+
+    // The failure isn't cached.
+    let (results, fetches) = get_concurrently(cache, Ok("value")).await;
+    assert_eq!(fetches, 1);
+    assert!(results.iter().all(|res| res == &Ok(vec!["value".into()])));
+}
+
+#[tokio::test]
+async fn panicked_refreshes_are_retried() {
+    let dir = TempDir::new("cache-panics");
+
+    let cache = cache(1, Duration::ZERO);
+    assert_eq!(get(cache, &dir, "first").await, ["first"]);
+
+    let refreshed = cache
+        .get(&supervisor(), Some(dir.0.clone()), "key".into(), || async {
+            panic!("refresh failed")
+        })
+        .await;
+    assert_eq!(refreshed.expect("stale value").value, ["first"]);
+
+    // Once the panicked refresh has been cleaned up, the key is refreshed again.
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while get(cache, &dir, "second").await == ["first"] {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("value is refreshed");
+}
+
+#[tokio::test]
+async fn shutdown_cancels_refreshes() {
+    let dir = TempDir::new("cache-shutdown");
+
+    let cache = cache(1, Duration::ZERO);
+    assert_eq!(get(cache, &dir, "first").await, ["first"]);
+
+    // Start a refresh that would never finish.
+    let shutdown = Shutdown::new();
+    let started = Arc::new(AtomicUsize::new(0));
+    let refresh_started = started.clone();
+    let refreshed = cache
+        .get(
+            &Supervisor::new(shutdown.clone()),
+            Some(dir.0.clone()),
+            "key".into(),
+            move || async move {
+                refresh_started.fetch_add(1, Ordering::SeqCst);
+                std::future::pending().await
+            },
+        )
+        .await;
+    assert_eq!(refreshed.expect("stale value").value, ["first"]);
+    wait_until(|| started.load(Ordering::SeqCst) == 1).await;
+
+    // Shutdown waits for the refresh, and cancels it rather than running into the
+    // deadline.
+    let deadline = Duration::from_secs(10);
+    tokio::time::timeout(deadline / 2, shutdown.drain(deadline))
+        .await
+        .expect("refresh is cancelled");
+
+    // The cancelled refresh doesn't stop the key from being refreshed again.
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while get(cache, &dir, "second").await == ["first"] {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("value is refreshed");
+}
+
+#[tokio::test]
+async fn versions_invalidate_persisted_values() {
+    let dir = TempDir::new("cache-versions");
+
+    let cache = cache(1, TTL);
+    assert_eq!(get(cache, &dir, "first").await, ["first"]);
+
+    let upgraded = self::cache(2, TTL);
+    assert_eq!(get(upgraded, &dir, "second").await, ["second"]);
+    // Values persisted by the old version are removed.
+    assert!(!dir.0.join("test/v1").exists());
+    assert!(dir.0.join("test/v2").exists());
+}
+
+#[tokio::test]
+async fn aged_json_reports_age() {
+    let dir = TempDir::new("cache-aged-json");
+
+    let cached = cache(1, TTL)
+        .get(&supervisor(), Some(dir.0.clone()), "key".into(), || async {
+            Ok::<_, &str>(vec!["value".to_owned()])
+        })
+        .await
+        .expect("fetch succeeds")
+        .map(|value| serde_json::json!({ "values": value }));
+
+    let res = AgedJson(Some(cached)).into_response();
+    assert_eq!(res.headers()[DATA_AGE], "0");
+    let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+        .await
+        .expect("can read body");
+    let json = serde_json::from_slice::<Value>(&body).expect("valid JSON");
+    assert_eq!(json["values"][0], "value");
+    assert_eq!(json["data_age_secs"], 0);
+
+    // Until there is data, clients are asked to come back later.
+    let res = AgedJson::<Value>(None).into_response();
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(res.headers()[RETRY_AFTER], "30");
+    assert!(res.headers().get(DATA_AGE).is_none());
+}
@@ -0,0 +1,65 @@
+use std::net::IpAddr;
+
+use axum::http::{HeaderMap, HeaderValue};
+
+use crate::util::client_ip::TrustedProxies;
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().expect("valid")
+}
+
+fn headers(entries: &[(&'static str, &'static str)]) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for (name, value) in entries {
+        headers.append(*name, HeaderValue::from_static(value));
+    }
+    headers
+}
+
+#[test]
+fn untrusted_peers_are_the_client() {
+    let proxies: TrustedProxies = "172.16.0.0/12".parse().expect("valid");
+
+    let spoofed = headers(&[
+        ("fly-client-ip", "192.0.2.1"),
+        ("x-forwarded-for", "192.0.2.2"),
+    ]);
+    assert_eq!(
+        proxies.client_ip(ip("198.51.100.7"), &spoofed),
+        ip("198.51.100.7"),
+    );
+    assert_eq!(
+        TrustedProxies::default().client_ip(ip("172.16.0.1"), &spoofed),
+        ip("172.16.0.1"),
+    );
+}
+
+#[test]
+fn trusted_proxies_identify_the_client() {
+    let proxies: TrustedProxies = "172.16.0.0/12, fdaa::/16".parse().expect("valid");
+
+    // Fly-Client-IP takes precedence.
+    let req = headers(&[
+        ("fly-client-ip", "2001:db8::1"),
+        ("x-forwarded-for", "192.0.2.2"),
+    ]);
+    assert_eq!(proxies.client_ip(ip("fdaa::3"), &req), ip("2001:db8::1"));
+
+    // Otherwise, the last untrusted address in X-Forwarded-For is the client; anything
+    // before it was set by the client, and can't be believed.
+    let req = headers(&[
+        ("x-forwarded-for", "10.0.0.1, 192.0.2.2"),
+        ("x-forwarded-for", "172.16.5.5"),
+    ]);
+    assert_eq!(proxies.client_ip(ip("172.16.0.1"), &req), ip("192.0.2.2"));
+
+    // If every address is trusted, the earliest one is the client.
+    let req = headers(&[("x-forwarded-for", "172.16.9.9, 172.16.5.5")]);
+    assert_eq!(proxies.client_ip(ip("172.16.0.1"), &req), ip("172.16.9.9"));
+
+    // Garbage stops the search.
+    let req = headers(&[("x-forwarded-for", "192.0.2.2, garbage, 172.16.5.5")]);
+    assert_eq!(proxies.client_ip(ip("172.16.0.1"), &req), ip("172.16.5.5"));
+    let req = headers(&[("fly-client-ip", "garbage")]);
+    assert_eq!(proxies.client_ip(ip("172.16.0.1"), &req), ip("172.16.0.1"));
+}
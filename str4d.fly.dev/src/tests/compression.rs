@@ -0,0 +1,104 @@
+use std::{
+    convert::Infallible,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    http::{
+        HeaderValue, StatusCode,
+        header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_TYPE, VARY},
+    },
+    response::Response,
+};
+use hyper::body::Frame;
+use tower_http::compression::predicate::Predicate;
+
+use super::{MockUpstream, TestApp, github_graphql};
+use crate::util::compression::Compressible;
+
+#[tokio::test]
+async fn large_responses_are_compressed() {
+    let upstream = MockUpstream::new()
+        .route("/graphql", github_graphql())
+        .start()
+        .await;
+    let app = TestApp::new(&upstream);
+    app.wait_for_data("rust.rfc.observer", "/api/data").await;
+
+    for encoding in ["br", "gzip", "zstd"] {
+        let accept = HeaderValue::from_static(encoding);
+        let res = app
+            .get_with(
+                "rust.rfc.observer",
+                "/api/data",
+                &[(ACCEPT_ENCODING, &accept)],
+            )
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[CONTENT_ENCODING], encoding);
+        assert_eq!(res.headers()[VARY], "accept-encoding");
+    }
+
+    // Clients that don't accept compressed responses don't get them.
+    let res = app.get_with("rust.rfc.observer", "/api/data", &[]).await;
+    assert!(res.headers().get(CONTENT_ENCODING).is_none());
+}
+
+#[tokio::test]
+async fn small_responses_are_not_compressed() {
+    let app = TestApp::new(&MockUpstream::new().start().await);
+
+    let accept = HeaderValue::from_static("gzip");
+    let res = app
+        .get_with("atp.fyi", "/_health", &[(ACCEPT_ENCODING, &accept)])
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.headers().get(CONTENT_ENCODING).is_none());
+}
+
+#[test]
+fn only_known_content_types_are_compressed() {
+    let response = |content_type: &str, body: Body| {
+        Response::builder()
+            .header(CONTENT_TYPE, content_type)
+            .body(body)
+            .expect("valid")
+    };
+    let large = || Body::from(vec![b'a'; 4096]);
+
+    assert!(Compressible.should_compress(&response("text/html; charset=utf-8", large())));
+    assert!(Compressible.should_compress(&response("application/json", large())));
+    assert!(!Compressible.should_compress(&response("image/png", large())));
+    assert!(!Compressible.should_compress(&response("application/octet-stream", large())));
+
+    // Already-compressed responses are left alone.
+    let mut compressed = response("application/json", large());
+    compressed
+        .headers_mut()
+        .insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+    assert!(!Compressible.should_compress(&compressed));
+
+    // So are streamed responses (which don't have a known size).
+    let streamed = Response::builder()
+        .header(CONTENT_TYPE, "text/html")
+        .body(Streamed)
+        .expect("valid");
+    assert!(!Compressible.should_compress(&streamed));
+}
+
+/// A body of unknown size.
+struct Streamed;
+
+impl HttpBody for Streamed {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+        Poll::Ready(None)
+    }
+}
@@ -0,0 +1,70 @@
+use axum::{Json, extract::RawQuery, http::StatusCode, routing::get};
+use serde_json::{Value, json};
+
+use super::{MockUpstream, TestApp, fixture};
+
+/// Serves `getProfiles` for the requested actors, from the profiles we have fixtures for.
+async fn get_profiles(RawQuery(query): RawQuery) -> Json<Value> {
+    let actors = query
+        .unwrap_or_default()
+        .split('&')
+        .filter_map(|param| param.strip_prefix("actors="))
+        .map(String::from)
+        .collect::<Vec<_>>();
+    // The AppView rejects requests for more than 25 actors.
+    assert!(actors.len() <= 25, "requested {} actors", actors.len());
+
+    let profiles = serde_json::from_str::<Value>(&fixture("eprint/profiles.json"))
+        .expect("valid JSON")["profiles"]
+        .as_array()
+        .expect("present")
+        .iter()
+        .filter(|profile| actors.iter().any(|actor| profile["did"] == actor.as_str()))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    Json(json!({ "profiles": profiles }))
+}
+
+#[tokio::test]
+async fn authors() {
+    let upstream = MockUpstream::new()
+        .route(
+            "/authors",
+            get(|| async { super::json("eprint/authors.json") }),
+        )
+        .route(
+            "/appview/xrpc/app.bsky.actor.getProfiles",
+            get(get_profiles),
+        )
+        .start()
+        .await;
+    let app = TestApp::new(&upstream);
+
+    let (status, body) = app.get("cryptography.social", "/").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!body.contains("Failed to fetch authors"));
+
+    // Authors from both profile requests are present, including those without a profile.
+    for i in 0..30 {
+        assert!(
+            body.contains(&format!("Author {i:02}")),
+            "missing author {i}"
+        );
+    }
+    assert!(body.contains("https://bsky.app/profile/did:plc:author29"));
+    // Avatars are shown as thumbnails.
+    assert!(body.contains(
+        "https://cdn.example.com/img/avatar_thumbnail/plain/did:plc:author00/bafkreiavatar@jpeg"
+    ));
+    assert!(!body.contains("img/avatar/"));
+}
+
+#[tokio::test]
+async fn authors_upstream_failure() {
+    let app = TestApp::new(&MockUpstream::new().start().await);
+
+    let (status, body) = app.get("cryptography.social", "/").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("Error: Failed to fetch authors from backend."));
+}
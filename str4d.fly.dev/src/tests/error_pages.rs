@@ -0,0 +1,122 @@
+use axum::{
+    Router,
+    body::Body,
+    http::{
+        HeaderValue, Request, StatusCode,
+        header::{CONTENT_TYPE, RETRY_AFTER},
+    },
+    response::Response,
+    routing::get,
+};
+use serde_json::Value;
+use tower::ServiceExt;
+
+use super::{MockUpstream, TestApp};
+use crate::util::{
+    error::{DEFAULT, Error},
+    request_id::X_REQUEST_ID,
+};
+
+async fn body(res: Response) -> String {
+    let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+        .await
+        .expect("can read body");
+    String::from_utf8(body.to_vec()).expect("UTF-8")
+}
+
+/// Returns the request ID shown on an error page.
+fn request_id(page: &str) -> &str {
+    let (_, rest) = page
+        .split_once("request ID <code>")
+        .expect("has request ID");
+    let (id, _) = rest.split_once("</code>").expect("valid");
+    id
+}
+
+#[tokio::test]
+async fn handler_errors_render_site_pages() {
+    let app = TestApp::new(&MockUpstream::new().start().await);
+
+    // The groups haven't been fetched yet.
+    let res = app.get_with("ietf.rfc.observer", "/", &[]).await;
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(res.headers()[CONTENT_TYPE], "text/html; charset=utf-8");
+    assert_eq!(res.headers()[RETRY_AFTER], "30");
+    let header = res.headers()[X_REQUEST_ID].clone();
+    let page = body(res).await;
+    assert!(page.contains("<title>Service Unavailable - RFC Observer</title>"));
+    assert!(page.contains("We are still fetching this data. Please try again shortly."));
+    assert_eq!(request_id(&page).len(), 32);
+    // The ID is also returned in a header, for API clients.
+    assert_eq!(header, request_id(&page));
+
+    // Each request has its own ID.
+    let (_, other) = app.get("ietf.rfc.observer", "/").await;
+    assert_ne!(request_id(&page), request_id(&other));
+}
+
+#[tokio::test]
+async fn bare_errors_render_site_pages() {
+    let app = TestApp::new(&MockUpstream::new().start().await);
+
+    for (host, title) in [
+        ("atp.fyi", "Not Found - Bluesky & ATProto FYI"),
+        ("cryptography.design", "Not Found - Cryptography Design"),
+        ("cryptography.social", "Not Found - Cryptography Social"),
+        ("s-s.sh", "Not Found - sssh"),
+        ("siso.dev", "Not Found - SISO"),
+        ("str4d.xyz", "Not Found - Jack Grigg / str4d"),
+        ("go.rfc.observer", "Not Found - RFC Observer"),
+        // Hosts without a router of their own get the default page.
+        ("blog.jackgrigg.com", "404 Not Found"),
+    ] {
+        let (status, page) = app.get(host, "/missing").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(page.contains(&format!("<title>{title}</title>")), "{host}");
+        assert!(page.contains("There is nothing here."), "{host}");
+    }
+
+    let (status, page) = app.get("unknown.example.com", "/").await;
+    assert_eq!(status, StatusCode::MISDIRECTED_REQUEST);
+    assert!(page.contains("This server does not host that site."));
+}
+
+#[tokio::test]
+async fn api_errors_are_problem_details() {
+    let app = TestApp::new(&MockUpstream::new().start().await);
+
+    let res = app.get_with("go.rfc.observer", "/api/missing", &[]).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    assert_eq!(res.headers()[CONTENT_TYPE], "application/problem+json");
+    let problem: Value = serde_json::from_str(&body(res).await).expect("JSON");
+    assert_eq!(problem["status"], 404);
+    assert_eq!(problem["title"], "Not Found");
+    assert_eq!(problem["detail"], "There is nothing here.");
+    assert_eq!(problem["request_id"].as_str().map(str::len), Some(32));
+}
+
+#[tokio::test]
+async fn error_headers_are_kept() {
+    let router = DEFAULT.apply(Router::new().route(
+        "/",
+        get(|| async {
+            (
+                [(RETRY_AFTER, HeaderValue::from_static("30"))],
+                Error::new(StatusCode::SERVICE_UNAVAILABLE).detail("Back soon."),
+            )
+        }),
+    ));
+
+    let req = Request::builder()
+        .uri("/")
+        .body(Body::empty())
+        .expect("valid");
+    let res = router.oneshot(req).await.expect("infallible");
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(res.headers()[RETRY_AFTER], "30");
+    let page = body(res).await;
+    assert!(page.contains("503 Service Unavailable"));
+    assert!(page.contains("Back soon."));
+    // This request didn't go through the app, so it has no ID.
+    assert!(!page.contains("request ID"));
+}
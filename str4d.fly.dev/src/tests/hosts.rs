@@ -0,0 +1,319 @@
+use axum::{
+    Router,
+    body::Body,
+    extract::Request,
+    http::{
+        StatusCode,
+        header::{HOST, LOCATION},
+    },
+    routing::get,
+};
+use tower::ServiceExt;
+
+use super::{MockUpstream, TestApp};
+
+use crate::util::{
+    Multiplexer, Subdomain,
+    hosts::{HostsConfig, Routers},
+};
+
+/// Builds a [`Multiplexer`] from the given hosts configuration, with a single router
+/// named `example`.
+fn from_config(config: &str) -> Result<Multiplexer<()>, String> {
+    let config: HostsConfig = toml::from_str(config).expect("valid TOML");
+    let routers = Routers::new().register("example", Router::new());
+    Multiplexer::from_config(config, routers).map_err(|e| e.to_string())
+}
+
+#[test]
+fn invalid_configs_are_rejected() {
+    for (config, error) in [
+        (
+            r#"
+            [[host]]
+            name = "example.com"
+            router = "example"
+
+            [[host]]
+            name = "example.com"
+            router = "example"
+            "#,
+            "Host example.com is configured more than once",
+        ),
+        (
+            r#"
+            [[host]]
+            name = "example.com"
+            aliases = ["www.example.com", "example.com"]
+            router = "example"
+            "#,
+            "Host example.com is configured more than once",
+        ),
+        (
+            r#"
+            [[host]]
+            name = "example.com"
+            router = "example"
+
+            [[host]]
+            name = "Example.COM."
+            router = "example"
+            "#,
+            "Host Example.COM. is configured more than once",
+        ),
+        (
+            r#"
+            [[host]]
+            name = "example.com:443"
+            router = "example"
+
+            [[host]]
+            name = "foo.example.com"
+            aliases = ["example.com"]
+            router = "example"
+            "#,
+            "Host example.com is configured more than once",
+        ),
+        (
+            r#"
+            [[host]]
+            name = "example.com"
+            router = "missing"
+            "#,
+            "Host example.com refers to unknown router missing",
+        ),
+        (
+            r#"
+            [[host]]
+            name = "example.com"
+            aliases = ["www.example.com"]
+            "#,
+            "Host example.com has neither a router nor redirects, so it and its aliases point nowhere",
+        ),
+        (
+            r#"
+            [[host]]
+            name = "example.com"
+
+            [host.redirects]
+            "/" = { kind = "temporary", to = "https://example.org/\n" }
+            "#,
+            "Redirect target for path \"/\" on host example.com is not a valid URI",
+        ),
+        (
+            r#"
+            [[host]]
+            name = "example.com"
+
+            [host.redirects]
+            "/{id}" = { kind = "gone" }
+            "#,
+            "Redirect path \"/{id}\" for host example.com must be an absolute path without parameters",
+        ),
+        (
+            r#"
+            [[host]]
+            name = "www.*.example.com"
+            router = "example"
+            "#,
+            "Host name \"www.*.example.com\" is invalid (wildcards are only allowed as a leading `*.`)",
+        ),
+        (
+            r#"
+            [[host]]
+            name = "*.example.com"
+            aliases = ["example.org"]
+            router = "example"
+            "#,
+            "Wildcard host *.example.com has aliases, but there is no single host to redirect them to",
+        ),
+    ] {
+        match from_config(config) {
+            Ok(_) => panic!("Config should be rejected with {error:?}:\n{config}"),
+            Err(e) => assert_eq!(e, error),
+        }
+    }
+}
+
+#[test]
+fn unknown_fields_are_rejected() {
+    for config in [
+        "[[hosts]]\nname = \"example.com\"\n",
+        "[[host]]\nname = \"example.com\"\nrouters = \"example\"\n",
+        "[[host]]\nname = \"example.com\"\n[host.redirects]\n\"/\" = { kind = \"found\", to = \"/\" }\n",
+    ] {
+        assert!(
+            toml::from_str::<HostsConfig>(config).is_err(),
+            "Config should not parse:\n{config}",
+        );
+    }
+}
+
+#[test]
+fn missing_config_is_rejected() {
+    let e = HostsConfig::load("/nonexistent/hosts.toml").expect_err("file is missing");
+    assert!(
+        e.to_string()
+            .starts_with("Failed to read /nonexistent/hosts.toml: "),
+        "{e}",
+    );
+}
+
+/// Returns a router that responds with `name`, and the matched subdomain (if any).
+fn named(name: &'static str) -> Router {
+    Router::new().route(
+        "/",
+        get(move |req: Request| async move {
+            match req.extensions().get::<Subdomain>() {
+                Some(Subdomain(subdomain)) => format!("{name} ({subdomain})"),
+                None => name.to_owned(),
+            }
+        }),
+    )
+}
+
+/// Requests `/` from `host`, returning the response status and body.
+async fn get_root(app: &Multiplexer<()>, host: &str) -> (StatusCode, String) {
+    let req = Request::builder()
+        .uri("/")
+        .header(HOST, host)
+        .body(Body::empty())
+        .expect("valid");
+    let res = app.clone().oneshot(req).await.expect("infallible");
+    let status = res.status();
+    let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+        .await
+        .expect("can read body");
+    (status, String::from_utf8(body.to_vec()).expect("UTF-8"))
+}
+
+#[tokio::test]
+async fn hosts_are_normalized() {
+    let app = Multiplexer::new().handle("Example.COM", named("example"));
+
+    for host in [
+        "example.com",
+        "EXAMPLE.com",
+        "example.com:8080",
+        "example.com.",
+        "Example.Com.:443",
+    ] {
+        assert_eq!(
+            get_root(&app, host).await,
+            (StatusCode::OK, "example".into()),
+            "{host}",
+        );
+    }
+
+    for host in ["www.example.com", "example.com..", "example.co"] {
+        let (status, _) = get_root(&app, host).await;
+        assert_eq!(status, StatusCode::MISDIRECTED_REQUEST, "{host}");
+    }
+}
+
+#[tokio::test]
+async fn most_specific_host_wins() {
+    // Registered from least to most specific, to check that order doesn't matter.
+    let app = Multiplexer::new()
+        .handle("*.example.com", named("wildcard"))
+        .handle("*.foo.example.com", named("foo wildcard"))
+        .handle("bar.foo.example.com", named("bar"));
+
+    for (host, expected) in [
+        ("bar.foo.example.com", "bar"),
+        ("BAR.foo.example.com.", "bar"),
+        ("baz.foo.example.com", "foo wildcard (baz)"),
+        ("a.b.foo.example.com:443", "foo wildcard (a.b)"),
+        ("foo.example.com", "wildcard (foo)"),
+        ("Other.Example.com", "wildcard (other)"),
+    ] {
+        assert_eq!(
+            get_root(&app, host).await,
+            (StatusCode::OK, expected.into()),
+            "{host}",
+        );
+    }
+
+    // A wildcard doesn't match its own suffix.
+    let (status, _) = get_root(&app, "example.com").await;
+    assert_eq!(status, StatusCode::MISDIRECTED_REQUEST);
+}
+
+#[tokio::test]
+async fn redirects_have_the_configured_kind() {
+    let app = from_config(
+        r#"
+        [[host]]
+        name = "example.com"
+        aliases = ["www.example.com"]
+        alias_redirect = "permanent"
+
+        [host.redirects]
+        "/temporary" = { kind = "temporary", to = "https://example.org/temporary" }
+        "/permanent" = { kind = "permanent", to = "https://example.org/permanent" }
+        "/moved" = { kind = "moved-permanently", to = "https://example.org/moved" }
+        "/retired" = { kind = "gone" }
+        "#,
+    )
+    .expect("valid");
+
+    for (host, path, status, location) in [
+        (
+            "example.com",
+            "/temporary",
+            StatusCode::TEMPORARY_REDIRECT,
+            Some("https://example.org/temporary"),
+        ),
+        (
+            "example.com",
+            "/permanent",
+            StatusCode::PERMANENT_REDIRECT,
+            Some("https://example.org/permanent"),
+        ),
+        (
+            "example.com",
+            "/moved",
+            StatusCode::MOVED_PERMANENTLY,
+            Some("https://example.org/moved"),
+        ),
+        ("example.com", "/retired", StatusCode::GONE, None),
+        (
+            "www.example.com",
+            "/retired?q=1",
+            StatusCode::PERMANENT_REDIRECT,
+            Some("https://example.com/retired?q=1"),
+        ),
+    ] {
+        let req = Request::builder()
+            .uri(path)
+            .header(HOST, host)
+            .body(Body::empty())
+            .expect("valid");
+        let res = app.clone().oneshot(req).await.expect("infallible");
+        assert_eq!(res.status(), status, "{host}{path}");
+        assert_eq!(
+            res.headers()
+                .get(LOCATION)
+                .map(|location| location.to_str().expect("ASCII")),
+            location,
+            "{host}{path}",
+        );
+    }
+}
+
+#[tokio::test]
+async fn project_pages_redirect_to_github() {
+    let app = TestApp::new(&MockUpstream::new().start().await);
+
+    for (path, location) in [
+        ("/rage", "https://github.com/str4d/rage"),
+        (
+            "/wage/report",
+            "https://github.com/str4d/wage/issues/new/choose",
+        ),
+    ] {
+        let res = app.get_with("str4d.xyz", path, &[]).await;
+        assert_eq!(res.status(), StatusCode::TEMPORARY_REDIRECT, "{path}");
+        assert_eq!(res.headers()[LOCATION], location, "{path}");
+    }
+}
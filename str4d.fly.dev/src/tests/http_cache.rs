@@ -0,0 +1,100 @@
+use axum::{
+    http::{
+        HeaderValue, StatusCode,
+        header::{CACHE_CONTROL, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    },
+    response::Response,
+};
+
+use super::{MockUpstream, TestApp, github_graphql};
+
+const DATA_HOST: &str = "rust.rfc.observer";
+
+async fn body(res: Response) -> String {
+    let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+        .await
+        .expect("can read body");
+    String::from_utf8(body.to_vec()).expect("UTF-8")
+}
+
+#[tokio::test]
+async fn conditional_requests() {
+    let upstream = MockUpstream::new()
+        .route("/graphql", github_graphql())
+        .start()
+        .await;
+    let app = TestApp::new(&upstream);
+    app.wait_for_data(DATA_HOST, "/api/data").await;
+
+    let res = app.get_with(DATA_HOST, "/api/data", &[]).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[CACHE_CONTROL], "public, max-age=600");
+    let etag = res.headers()[ETAG].clone();
+    let last_modified = res.headers()[LAST_MODIFIED].clone();
+
+    // The ETag doesn't change as the data ages.
+    let res = app.get_with(DATA_HOST, "/api/data", &[]).await;
+    assert_eq!(res.headers()[ETAG], etag);
+
+    let res = app
+        .get_with(DATA_HOST, "/api/data", &[(IF_NONE_MATCH, &etag)])
+        .await;
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(res.headers()[ETAG], etag);
+    assert_eq!(res.headers()[CACHE_CONTROL], "public, max-age=600");
+    assert!(body(res).await.is_empty());
+
+    let outdated = HeaderValue::from_static("\"outdated\"");
+    let res = app
+        .get_with(DATA_HOST, "/api/data", &[(IF_NONE_MATCH, &outdated)])
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(!body(res).await.is_empty());
+
+    let res = app
+        .get_with(
+            DATA_HOST,
+            "/api/data",
+            &[(IF_MODIFIED_SINCE, &last_modified)],
+        )
+        .await;
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+    // If-None-Match takes precedence over If-Modified-Since.
+    let res = app
+        .get_with(
+            DATA_HOST,
+            "/api/data",
+            &[
+                (IF_NONE_MATCH, &outdated),
+                (IF_MODIFIED_SINCE, &last_modified),
+            ],
+        )
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn pages_have_etags() {
+    let app = TestApp::new(&MockUpstream::new().start().await);
+
+    let res = app.get_with("siso.dev", "/", &[]).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[CACHE_CONTROL], "public, max-age=60");
+    let etag = res.headers()[ETAG].clone();
+
+    let res = app
+        .get_with("siso.dev", "/", &[(IF_NONE_MATCH, &etag)])
+        .await;
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+}
+
+#[tokio::test]
+async fn missing_data_is_not_cacheable() {
+    let app = TestApp::new(&MockUpstream::new().start().await);
+
+    let res = app.get_with(DATA_HOST, "/api/data", &[]).await;
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert!(res.headers().get(CACHE_CONTROL).is_none());
+    assert!(res.headers().get(ETAG).is_none());
+}
@@ -0,0 +1,191 @@
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    sync::Arc,
+};
+
+use axum::{
+    Router,
+    body::Body,
+    extract::{ConnectInfo, Request},
+    http::{
+        StatusCode,
+        header::{CONTENT_TYPE, HOST, RETRY_AFTER},
+    },
+    middleware,
+    response::Response,
+    routing::get,
+};
+use tokio::sync::Notify;
+use tower::ServiceExt;
+
+use super::{MockUpstream, TestApp, fly_trusted_proxies};
+use crate::util::{
+    Multiplexer,
+    hosts::{HostsConfig, Routers},
+    limits::{self, ConcurrencyLimit},
+};
+
+impl TestApp {
+    /// Requests `path` from `host`, as the client at `client`.
+    async fn get_from(&self, client: Ipv4Addr, host: &str, path: &str) -> Response {
+        let mut req = Request::builder()
+            .uri(path)
+            .header(HOST, host)
+            .body(Body::empty())
+            .expect("valid");
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from((client, 443))));
+        self.app.clone().oneshot(req).await.expect("infallible")
+    }
+}
+
+#[tokio::test]
+async fn clients_are_rate_limited_per_route() {
+    let app = TestApp::new(&MockUpstream::new().start().await);
+    let client = Ipv4Addr::new(192, 0, 2, 1);
+
+    // hosts.toml allows a burst of 5 requests.
+    for _ in 0..5 {
+        let res = app
+            .get_from(client, "ietf.rfc.observer", "/api/data/mockwg")
+            .await;
+        assert_ne!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    // The limit applies to the route, not to each acronym.
+    let res = app
+        .get_from(client, "ietf.rfc.observer", "/api/data/otherwg")
+        .await;
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    // At 10 requests per minute, the next token arrives within 6 seconds.
+    let retry_after = res.headers()[RETRY_AFTER].to_str().expect("ASCII");
+    assert!((1..=6).contains(&retry_after.parse::<u64>().expect("seconds")));
+    assert_eq!(res.headers()[CONTENT_TYPE], "application/problem+json");
+
+    // Other routes, and other clients, are unaffected.
+    let res = app.get_from(client, "ietf.rfc.observer", "/").await;
+    assert_ne!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    let res = app
+        .get_from(
+            Ipv4Addr::new(192, 0, 2, 2),
+            "ietf.rfc.observer",
+            "/api/data/mockwg",
+        )
+        .await;
+    assert_ne!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn clients_behind_the_proxy_are_rate_limited_separately() {
+    let app = TestApp::with_config(&MockUpstream::new().start().await, |config| {
+        config.trusted_proxies = fly_trusted_proxies();
+    });
+    let client = Ipv4Addr::new(192, 0, 2, 1);
+
+    // Requests from the same client reach us over different connections from the proxy.
+    for proxy in [
+        "fdaa:0:1::2",
+        "fdaa:0:1::3",
+        "172.16.0.2",
+        "fdaa:0:1::2",
+        "172.16.0.3",
+    ] {
+        let res = app
+            .get_via_proxy(
+                proxy.parse().unwrap(),
+                client,
+                "ietf.rfc.observer",
+                "/api/data/mockwg",
+            )
+            .await;
+        assert_ne!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+    let res = app
+        .get_via_proxy(
+            "fdaa:0:1::2".parse().unwrap(),
+            client,
+            "ietf.rfc.observer",
+            "/api/data/mockwg",
+        )
+        .await;
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    // Other clients, via the same proxy, have their own limits.
+    let res = app
+        .get_via_proxy(
+            "fdaa:0:1::2".parse().unwrap(),
+            Ipv4Addr::new(192, 0, 2, 2),
+            "ietf.rfc.observer",
+            "/api/data/mockwg",
+        )
+        .await;
+    assert_ne!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn excess_requests_are_shed() {
+    let started = Arc::new(Notify::new());
+    let release = Arc::new(Notify::new());
+    let (handler_started, handler_release) = (started.clone(), release.clone());
+    let router = Router::new()
+        .route(
+            "/",
+            get(move || async move {
+                handler_started.notify_one();
+                handler_release.notified().await;
+                "done"
+            }),
+        )
+        .layer(middleware::from_fn_with_state(
+            ConcurrencyLimit::new(1),
+            limits::shed,
+        ));
+    let get = |router: Router| async move {
+        let req = Request::builder()
+            .uri("/")
+            .body(Body::empty())
+            .expect("valid");
+        router.oneshot(req).await.expect("infallible")
+    };
+
+    let first = tokio::spawn(get(router.clone()));
+    started.notified().await;
+
+    let res = get(router.clone()).await;
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(res.headers()[RETRY_AFTER], "1");
+
+    release.notify_one();
+    assert_eq!(first.await.expect("no panic").status(), StatusCode::OK);
+    // Capacity is freed once a request has been handled.
+    let next = tokio::spawn(get(router));
+    started.notified().await;
+    release.notify_one();
+    assert_eq!(next.await.expect("no panic").status(), StatusCode::OK);
+}
+
+#[test]
+fn invalid_rate_limits_are_rejected() {
+    for (route, limit) in [
+        ("api/data", "{ per_minute = 10, burst = 5 }"),
+        ("/api/data", "{ per_minute = 0, burst = 5 }"),
+        ("/api/data", "{ per_minute = 10, burst = 0 }"),
+    ] {
+        let config: HostsConfig = toml::from_str(&format!(
+            "[[host]]\nname = \"example.com\"\nrouter = \"example\"\n\n\
+             [host.rate_limits]\n{route:?} = {limit}\n",
+        ))
+        .expect("valid TOML");
+        let routers = Routers::new().register("example", Router::new());
+
+        let Err(e) = Multiplexer::from_config(config, routers) else {
+            panic!("{limit} for {route:?} is not a valid rate limit");
+        };
+        assert_eq!(
+            e.to_string(),
+            format!(
+                "Rate limit for route {route:?} on host example.com must be for an absolute path, and allow at least one request",
+            ),
+        );
+    }
+}
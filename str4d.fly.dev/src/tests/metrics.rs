@@ -0,0 +1,153 @@
+use axum::{http::StatusCode, routing::get};
+use metrics_util::{
+    CompositeKey,
+    debugging::{DebugValue, DebuggingRecorder},
+};
+
+use super::{MockUpstream, TestApp};
+use crate::{
+    config::{Outbound, Recording},
+    util::{
+        HTTP_REQUEST_DURATION, HTTP_REQUESTS,
+        upstream::{
+            self, UPSTREAM_ERRORS, UPSTREAM_REQUESTS, UPSTREAM_RESPONSE_BYTES, UPSTREAM_RETRIES,
+        },
+    },
+};
+
+/// Returns the labels of the given key as `(host, path, method, status)`.
+fn labels(key: &CompositeKey) -> (String, String, String, String) {
+    let label = |name: &str| {
+        key.key()
+            .labels()
+            .find(|label| label.key() == name)
+            .unwrap_or_else(|| panic!("missing label {name}"))
+            .value()
+            .to_owned()
+    };
+    (
+        label("host"),
+        label("path"),
+        label("method"),
+        label("status"),
+    )
+}
+
+#[tokio::test]
+async fn requests_are_labelled_by_route() {
+    // Tests run on a single-threaded runtime, so this captures everything the app
+    // records during the test.
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    let _guard = metrics::set_default_local_recorder(&recorder);
+
+    let app = TestApp::new(&MockUpstream::new().start().await);
+    for (host, path, status) in [
+        ("str4d.xyz", "/", StatusCode::OK),
+        ("str4d.xyz", "/?utm_source=example", StatusCode::OK),
+        ("str4d.xyz", "/wp-login.php", StatusCode::NOT_FOUND),
+        ("atp.fyi", "/_health", StatusCode::OK),
+        ("probe.example.com", "/", StatusCode::MISDIRECTED_REQUEST),
+    ] {
+        assert_eq!(app.get(host, path).await.0, status, "{host}{path}");
+    }
+
+    let mut requests = vec![];
+    let mut durations = vec![];
+    for (key, _, _, value) in snapshotter.snapshot().into_vec() {
+        match value {
+            DebugValue::Counter(count) if key.key().name() == HTTP_REQUESTS => {
+                requests.push((labels(&key), count))
+            }
+            DebugValue::Histogram(values) if key.key().name() == HTTP_REQUEST_DURATION => {
+                durations.push((labels(&key), values.len() as u64))
+            }
+            _ => (),
+        }
+    }
+    requests.sort();
+    durations.sort();
+
+    let series = |host: &str, path: &str, status: &str, count| {
+        (
+            (host.into(), path.into(), "GET".into(), status.into()),
+            count,
+        )
+    };
+    let expected = vec![
+        series("*", "/_health", "2xx", 1),
+        series("str4d.xyz", "/", "2xx", 2),
+        series("str4d.xyz", "unmatched", "4xx", 1),
+        series("unmatched", "unmatched", "4xx", 1),
+    ];
+    assert_eq!(requests, expected);
+    assert_eq!(durations, expected);
+}
+
+#[tokio::test]
+async fn upstream_requests_are_labelled_by_upstream() {
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    let _guard = metrics::set_default_local_recorder(&recorder);
+
+    let base_url = MockUpstream::new()
+        .route("/ok", get(|| async { "0123456789" }))
+        .start()
+        .await;
+    let client = upstream::Client::new(&Recording::default(), &Outbound::default()).expect("valid");
+
+    let mock = client.named("mock");
+    for path in ["/ok", "/ok", "/missing"] {
+        mock.get(format!("{base_url}{path}"))
+            .send()
+            .await
+            .expect("can send");
+    }
+    // Nothing is listening on port 1.
+    assert!(
+        client
+            .named("unreachable")
+            .get("http://127.0.0.1:1/")
+            .send()
+            .await
+            .is_err()
+    );
+
+    let mut counters = snapshotter
+        .snapshot()
+        .into_vec()
+        .into_iter()
+        .filter_map(|(key, _, _, value)| match value {
+            DebugValue::Counter(count) => {
+                let key = key.key();
+                let mut labels = key
+                    .labels()
+                    .map(|label| format!("{}={}", label.key(), label.value()))
+                    .collect::<Vec<_>>();
+                labels.sort();
+                Some((key.name().to_owned(), labels.join(","), count))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    counters.sort();
+
+    let counter = |name: &str, labels: &str, count| (name.to_owned(), labels.to_owned(), count);
+    assert_eq!(
+        counters,
+        vec![
+            counter(UPSTREAM_ERRORS, "kind=connect,upstream=unreachable", 1),
+            counter(UPSTREAM_REQUESTS, "method=GET,status=2xx,upstream=mock", 2),
+            counter(UPSTREAM_REQUESTS, "method=GET,status=4xx,upstream=mock", 1),
+            counter(
+                UPSTREAM_REQUESTS,
+                "method=GET,status=error,upstream=unreachable",
+                1
+            ),
+            // Two bodies of 10 bytes, and an empty 404 body.
+            counter(UPSTREAM_RESPONSE_BYTES, "upstream=mock", 20),
+            // The connection failure is retried (but the 404 isn't).
+            counter(UPSTREAM_RETRIES, "upstream=unreachable", 2),
+        ]
+    );
+}
@@ -0,0 +1,210 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use axum::{
+    http::StatusCode,
+    routing::{get, post},
+};
+use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+use super::MockUpstream;
+use crate::{
+    config::{Outbound, Recording},
+    util::upstream::{self, UPSTREAM_CIRCUIT_REJECTED, UPSTREAM_CIRCUIT_STATE},
+};
+
+fn client(policy: Outbound) -> upstream::Client {
+    upstream::Client::new(&Recording::default(), &policy).expect("valid")
+}
+
+#[tokio::test]
+async fn failed_gets_are_retried() {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let flaky_requests = Arc::new(AtomicUsize::new(0));
+    let base_url = MockUpstream::new()
+        .route(
+            "/flaky",
+            get(move || async move {
+                // Fails the first time.
+                match flaky_requests.fetch_add(1, Ordering::SeqCst) {
+                    0 => StatusCode::SERVICE_UNAVAILABLE,
+                    _ => StatusCode::OK,
+                }
+            }),
+        )
+        .route(
+            "/broken",
+            post(|| async { StatusCode::INTERNAL_SERVER_ERROR }),
+        )
+        .count_requests(requests.clone())
+        .start()
+        .await;
+    let mock = client(Outbound::default()).named("mock");
+
+    let res = mock
+        .get(format!("{base_url}/flaky"))
+        .send()
+        .await
+        .expect("can send");
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+    // Other requests might not be safe to repeat.
+    let res = mock
+        .post(format!("{base_url}/broken"))
+        .send()
+        .await
+        .expect("can send");
+    assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn slow_responses_time_out() {
+    let base_url = MockUpstream::new()
+        .route(
+            "/slow",
+            get(|| async {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+                "done"
+            }),
+        )
+        .start()
+        .await;
+    let mock = client(Outbound {
+        timeout_secs: 1,
+        retries: 0,
+        ..Outbound::default()
+    })
+    .named("mock");
+
+    let e = mock
+        .get(format!("{base_url}/slow"))
+        .send()
+        .await
+        .expect_err("times out");
+    assert!(e.is_timeout());
+}
+
+#[tokio::test]
+async fn failing_upstreams_are_cut_off() {
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    let _guard = metrics::set_default_local_recorder(&recorder);
+    let gauge = |name: &str| {
+        snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .find_map(|(key, _, _, value)| match value {
+                DebugValue::Gauge(value)
+                    if key.key().name() == UPSTREAM_CIRCUIT_STATE
+                        && key.key().labels().any(|label| label.value() == name) =>
+                {
+                    Some(value.into_inner())
+                }
+                _ => None,
+            })
+    };
+
+    let requests = Arc::new(AtomicUsize::new(0));
+    let healthy = Arc::new(AtomicBool::new(false));
+    let status = healthy.clone();
+    let base_url = MockUpstream::new()
+        .route(
+            "/",
+            get(move || async move {
+                if status.load(Ordering::SeqCst) {
+                    StatusCode::OK
+                } else {
+                    StatusCode::BAD_GATEWAY
+                }
+            }),
+        )
+        .count_requests(requests.clone())
+        .start()
+        .await;
+    let client = client(Outbound {
+        retries: 0,
+        breaker_threshold: 2,
+        breaker_cooldown_secs: 1,
+        ..Outbound::default()
+    });
+    let get = |name: &'static str| {
+        let req = client.named(name).get(format!("{base_url}/"));
+        async move { req.send().await.expect("can send").status() }
+    };
+
+    for _ in 0..2 {
+        assert_eq!(get("mock").await, StatusCode::BAD_GATEWAY);
+    }
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+    assert_eq!(gauge("mock"), Some(2.0));
+
+    // The circuit is open, so requests fail without reaching the upstream.
+    assert_eq!(get("mock").await, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+    let rejected = snapshotter
+        .snapshot()
+        .into_vec()
+        .into_iter()
+        .find_map(|(key, _, _, value)| match value {
+            DebugValue::Counter(count) if key.key().name() == UPSTREAM_CIRCUIT_REJECTED => {
+                Some(count)
+            }
+            _ => None,
+        });
+    assert_eq!(rejected, Some(1));
+
+    // Each upstream has its own circuit.
+    assert_eq!(get("other").await, StatusCode::BAD_GATEWAY);
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+    // Once the cooldown has passed, a request is let through to check whether the
+    // upstream has recovered.
+    healthy.store(true, Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    assert_eq!(get("mock").await, StatusCode::OK);
+    assert_eq!(requests.load(Ordering::SeqCst), 4);
+    assert_eq!(gauge("mock"), Some(0.0));
+    assert_eq!(get("mock").await, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn failing_hosts_dont_cut_off_other_hosts() {
+    let failing_url = MockUpstream::new()
+        .route("/", get(|| async { StatusCode::BAD_GATEWAY }))
+        .start()
+        .await;
+    let requests = Arc::new(AtomicUsize::new(0));
+    let healthy_url = MockUpstream::new()
+        .route("/", get(|| async { StatusCode::OK }))
+        .count_requests(requests.clone())
+        .start()
+        .await;
+    let relay = client(Outbound {
+        retries: 0,
+        breaker_threshold: 2,
+        breaker_cooldown_secs: 60,
+        ..Outbound::default()
+    })
+    .named("relay");
+    let get = |base_url: &str| {
+        let req = relay.get(format!("{base_url}/"));
+        async move { req.send().await.expect("can send").status() }
+    };
+
+    for _ in 0..2 {
+        assert_eq!(get(&failing_url).await, StatusCode::BAD_GATEWAY);
+    }
+    assert_eq!(get(&failing_url).await, StatusCode::SERVICE_UNAVAILABLE);
+
+    // Requests to other hosts of the same upstream are still sent.
+    assert_eq!(get(&healthy_url).await, StatusCode::OK);
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+}
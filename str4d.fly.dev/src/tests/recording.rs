@@ -0,0 +1,140 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+
+use atrium_api::{client::AtpServiceClient, com::atproto::repo::list_records};
+use axum::{
+    Json,
+    http::StatusCode,
+    routing::{get, post},
+};
+use serde_json::{Value, json};
+
+use super::{MockUpstream, TempDir, json};
+use crate::{
+    config::{Outbound, Recording, RecordingMode},
+    util::upstream,
+};
+
+impl TempDir {
+    fn recording(&self, mode: RecordingMode) -> Recording {
+        Recording {
+            mode: Some(mode),
+            dir: self.0.clone(),
+        }
+    }
+}
+
+async fn fetch(client: &upstream::Client, base_url: &str) -> Vec<(StatusCode, String)> {
+    let mut responses = vec![];
+    for req in [
+        client.get(format!("{base_url}/text")),
+        client
+            .post(format!("{base_url}/echo"))
+            .json(&json!({ "n": 1 })),
+        client
+            .post(format!("{base_url}/echo"))
+            .json(&json!({ "n": 2 })),
+    ] {
+        let res = req.send().await.expect("can send");
+        responses.push((res.status(), res.text().await.expect("can read body")));
+    }
+    responses
+}
+
+#[tokio::test]
+async fn record_and_replay() {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let (text_requests, echo_requests) = (requests.clone(), requests.clone());
+    let base_url = MockUpstream::new()
+        .route(
+            "/text",
+            get(|| async move {
+                text_requests.fetch_add(1, Ordering::SeqCst);
+                "Hello from upstream!"
+            }),
+        )
+        .route(
+            "/echo",
+            post(|Json(body): Json<Value>| async move {
+                echo_requests.fetch_add(1, Ordering::SeqCst);
+                Json(json!({ "echo": body }))
+            }),
+        )
+        .start()
+        .await;
+    let dir = TempDir::new("recording-http");
+
+    let recorder =
+        upstream::Client::new(&dir.recording(RecordingMode::Record), &Outbound::default())
+            .expect("valid");
+    let recorded = fetch(&recorder, &base_url).await;
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+    assert_eq!(recorded[0], (StatusCode::OK, "Hello from upstream!".into()));
+    // Requests with different bodies are recorded separately.
+    assert_eq!(recorded[1].1, r#"{"echo":{"n":1}}"#);
+    assert_eq!(recorded[2].1, r#"{"echo":{"n":2}}"#);
+
+    let replayer =
+        upstream::Client::new(&dir.recording(RecordingMode::Replay), &Outbound::default())
+            .expect("valid");
+    assert_eq!(fetch(&replayer, &base_url).await, recorded);
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+    // Requests that weren't recorded fail without reaching the upstream.
+    let res = replayer
+        .post(format!("{base_url}/echo"))
+        .json(&json!({ "n": 3 }))
+        .send()
+        .await
+        .expect("can send");
+    assert_eq!(res.status(), StatusCode::BAD_GATEWAY);
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn record_and_replay_xrpc() {
+    let base_url = MockUpstream::new()
+        .route(
+            "/xrpc/com.atproto.repo.listRecords",
+            get(|| async { json("bluesky/listRecords.json") }),
+        )
+        .start()
+        .await;
+    let dir = TempDir::new("recording-xrpc");
+
+    let list_records = |client: upstream::Client| {
+        let base_url = base_url.clone();
+        async move {
+            AtpServiceClient::new(client.xrpc(base_url))
+                .service
+                .com
+                .atproto
+                .repo
+                .list_records(
+                    list_records::ParametersData {
+                        collection: "app.bsky.feed.post".parse().expect("valid"),
+                        cursor: None,
+                        limit: None,
+                        repo: "siso.dev".parse().expect("valid"),
+                        reverse: None,
+                    }
+                    .into(),
+                )
+                .await
+                .map(|output| output.data.records.len())
+        }
+    };
+
+    let recorder =
+        upstream::Client::new(&dir.recording(RecordingMode::Record), &Outbound::default())
+            .expect("valid");
+    let recorded = list_records(recorder).await.expect("upstream is up");
+    assert!(recorded > 0);
+
+    let replayer =
+        upstream::Client::new(&dir.recording(RecordingMode::Replay), &Outbound::default())
+            .expect("valid");
+    assert_eq!(list_records(replayer).await.expect("recorded"), recorded);
+}
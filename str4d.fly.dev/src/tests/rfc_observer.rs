@@ -0,0 +1,261 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+
+use axum::{
+    extract::{Path, RawQuery},
+    http::{
+        StatusCode,
+        header::{CONTENT_SECURITY_POLICY, CONTENT_TYPE, RETRY_AFTER},
+    },
+    routing::get,
+};
+
+use super::{MockUpstream, TestApp, github_graphql, json};
+use crate::rfc_observer::{ietf, rust};
+
+/// Mocks the datatracker API for a single working group, `mockwg`.
+///
+/// As with the real datatracker, looking up any other group finds nothing.
+fn datatracker() -> MockUpstream {
+    MockUpstream::new()
+        .route(
+            "/datatracker/api/v1/group/group/",
+            get(|RawQuery(query): RawQuery| async move {
+                let query = query.unwrap_or_default();
+                if query.contains("acronym=mockwg") {
+                    json("datatracker/group-mockwg.json")
+                } else if query.contains("acronym=") {
+                    json("datatracker/group-unknown.json")
+                } else if query.contains("offset=2") {
+                    json("datatracker/groups-2.json")
+                } else {
+                    json("datatracker/groups-1.json")
+                }
+            }),
+        )
+        .route(
+            "/datatracker/api/v1/doc/document/",
+            get(|RawQuery(query): RawQuery| async move {
+                let query = query.unwrap_or_default();
+                assert!(query.contains("group=1"), "unexpected query {query}");
+                if query.contains("type=rfc") {
+                    json("datatracker/documents-rfc.json")
+                } else {
+                    json("datatracker/documents-draft.json")
+                }
+            }),
+        )
+        .route(
+            "/datatracker/doc/{name}/doc.json",
+            get(|Path(name): Path<String>| async move {
+                json(&format!("datatracker/doc-{name}.json"))
+            }),
+        )
+}
+
+fn titles(data: &serde_json::Value, key: &str) -> Vec<String> {
+    data[key]
+        .as_array()
+        .unwrap_or_else(|| panic!("missing {key}"))
+        .iter()
+        .map(|item| item["title"].as_str().expect("title").to_owned())
+        .collect()
+}
+
+#[tokio::test]
+async fn index() {
+    let app = TestApp::new(&MockUpstream::new().start().await);
+
+    let (status, body) = app.get("rfc.observer", "/").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("ietf.rfc.observer"));
+}
+
+#[tokio::test]
+async fn unknown_observer() {
+    let app = TestApp::new(&MockUpstream::new().start().await);
+
+    let (status, body) = app.get("cobol.rfc.observer", "/").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(body.contains("There is no cobol.rfc.observer"));
+
+    // The error page has an inline stylesheet, so needs it to be allowed.
+    let res = app.get_with("cobol.rfc.observer", "/", &[]).await;
+    assert!(
+        res.headers()[CONTENT_SECURITY_POLICY]
+            .to_str()
+            .unwrap()
+            .ends_with("; style-src 'unsafe-inline'")
+    );
+}
+
+#[tokio::test]
+async fn go_proposals() {
+    let upstream = MockUpstream::new()
+        .route("/graphql", github_graphql())
+        .start()
+        .await;
+    let app = TestApp::new(&upstream);
+
+    // The proposals are fetched in the background.
+    let res = app.get_with("go.rfc.observer", "/api/data", &[]).await;
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(res.headers()[RETRY_AFTER], "30");
+    assert_eq!(res.headers()[CONTENT_TYPE], "application/problem+json");
+    app.wait_for_data("go.rfc.observer", "/api/data").await;
+
+    let (status, data) = app.get_json("go.rfc.observer", "/api/data").await;
+    assert_eq!(status, StatusCode::OK);
+    // Proposals from both pages are present.
+    assert_eq!(
+        titles(&data, "open"),
+        ["proposal: add a mock package", "proposal: held thing"],
+    );
+    assert_eq!(titles(&data, "closed"), ["proposal: accepted thing"]);
+}
+
+#[tokio::test]
+async fn go_upstream_failure() {
+    let app = TestApp::new(&MockUpstream::new().start().await);
+
+    let (status, _) = app.get_json("go.rfc.observer", "/api/data").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[tokio::test]
+async fn rust_tracking_issues() {
+    let upstream = MockUpstream::new()
+        .route("/graphql", github_graphql())
+        .start()
+        .await;
+    let app = TestApp::new(&upstream);
+    app.wait_for_data("rust.rfc.observer", "/api/data").await;
+
+    let (status, data) = app.get_json("rust.rfc.observer", "/api/data").await;
+    assert_eq!(status, StatusCode::OK);
+    // Tracking issues from both pages are present, except for the one that we can't
+    // find an RFC for.
+    assert_eq!(
+        titles(&data, "open"),
+        [
+            "Tracking Issue for mock feature",
+            "Tracking issue for another mock feature",
+        ],
+    );
+    assert_eq!(titles(&data, "closed"), ["Tracking issue for RFC 3002"]);
+}
+
+#[tokio::test]
+async fn rust_tracking_issues_are_warmed() {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let upstream = MockUpstream::new()
+        .route("/graphql", github_graphql())
+        .count_requests(requests.clone())
+        .start()
+        .await;
+    let app = TestApp::new(&upstream);
+
+    rust::warm(&app.client, &app.config, &app.supervisor)
+        .run_once()
+        .await
+        .expect("upstream is up");
+    let warmed = requests.load(Ordering::SeqCst);
+    assert!(warmed > 0);
+
+    // The warmed data is served without any more upstream requests.
+    let (status, data) = app.get_json("rust.rfc.observer", "/api/data").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(titles(&data, "closed"), ["Tracking issue for RFC 3002"]);
+    assert_eq!(requests.load(Ordering::SeqCst), warmed);
+}
+
+#[tokio::test]
+async fn rust_upstream_failure() {
+    let app = TestApp::new(&MockUpstream::new().start().await);
+
+    let (status, _) = app.get_json("rust.rfc.observer", "/api/data").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[tokio::test]
+async fn ietf_groups() {
+    let app = TestApp::new(&datatracker().start().await);
+    app.wait_for_data("ietf.rfc.observer", "/").await;
+
+    let (status, body) = app.get("ietf.rfc.observer", "/").await;
+    assert_eq!(status, StatusCode::OK);
+    // Groups from both pages are present.
+    assert!(body.contains("There are 2 active groups, and 1 inactive groups."));
+    assert!(body.contains("mockwg — Mock Working Group"));
+    assert!(body.contains("anotherwg — Another Working Group"));
+    assert!(body.contains("oldwg — Old Working Group"));
+
+    let (status, body) = app.get("ietf.rfc.observer", "/mockwg").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("Mock Working Group — RFC Observer"));
+}
+
+#[tokio::test]
+async fn ietf_documents() {
+    let app = TestApp::new(&datatracker().start().await);
+    app.wait_for_data("ietf.rfc.observer", "/api/data/mockwg")
+        .await;
+
+    let (status, data) = app.get_json("ietf.rfc.observer", "/api/data/mockwg").await;
+    assert_eq!(status, StatusCode::OK);
+    // Replaced drafts are ignored.
+    assert_eq!(titles(&data, "open"), ["Mock Protocol Extensions"]);
+    assert_eq!(titles(&data, "closed"), ["The Mock Protocol"]);
+}
+
+#[tokio::test]
+async fn ietf_unknown_group() {
+    let app = TestApp::new(&datatracker().start().await);
+    app.wait_for_data("ietf.rfc.observer", "/").await;
+
+    let (status, body) = app.get("ietf.rfc.observer", "/nonewg").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(body.contains("There is no IETF group named nonewg."));
+
+    // API requests get problem details rather than `null` data.
+    let (status, problem) = app.get_json("ietf.rfc.observer", "/api/data/nonewg").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(problem["detail"], "There is no IETF group named nonewg.");
+}
+
+#[tokio::test]
+async fn ietf_documents_are_warmed() {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let upstream = datatracker().count_requests(requests.clone()).start().await;
+    let app = TestApp::new(&upstream);
+
+    // Every active group is warmed, even though one of them can't be fetched.
+    let e = ietf::warm(&app.client, &app.config, &app.supervisor)
+        .run_once()
+        .await
+        .expect_err("anotherwg is missing");
+    assert!(format!("{e:#}").contains("1 of 2 tasks failed"));
+    assert!(format!("{e:#}").contains("Failed to get documents for anotherwg"));
+    let warmed = requests.load(Ordering::SeqCst);
+
+    // The warmed data is served without any more upstream requests.
+    let (status, body) = app.get("ietf.rfc.observer", "/").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("There are 2 active groups, and 1 inactive groups."));
+    let (status, data) = app.get_json("ietf.rfc.observer", "/api/data/mockwg").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(titles(&data, "closed"), ["The Mock Protocol"]);
+    assert_eq!(requests.load(Ordering::SeqCst), warmed);
+}
+
+#[tokio::test]
+async fn ietf_upstream_failure() {
+    let app = TestApp::new(&MockUpstream::new().start().await);
+
+    for path in ["/", "/mockwg", "/api/data/mockwg"] {
+        let (status, _) = app.get("ietf.rfc.observer", path).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE, "{path}");
+    }
+}
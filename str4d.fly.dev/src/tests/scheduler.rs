@@ -0,0 +1,89 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+use std::time::Duration;
+
+use axum::http::StatusCode;
+
+use super::{MockUpstream, TestApp, wait_until};
+use crate::util::scheduler::Job;
+
+#[tokio::test]
+async fn jobs_run_on_schedule() {
+    let app = TestApp::new(&MockUpstream::new().start().await);
+
+    let runs = Arc::new(AtomicUsize::new(0));
+    let job_runs = runs.clone();
+    Job::new("test job", Duration::from_millis(10), move |_| {
+        let runs = job_runs.clone();
+        async move {
+            runs.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    })
+    .jitter(Duration::from_millis(10))
+    .schedule(&app.supervisor);
+
+    wait_until(|| runs.load(Ordering::SeqCst) >= 3).await;
+    let status = app.supervisor.status("test job").expect("scheduled");
+    assert!(status.last_success.is_some());
+
+    app.stop().await;
+}
+
+#[tokio::test]
+async fn failing_jobs_are_reported() {
+    let app = TestApp::new(&MockUpstream::new().start().await);
+
+    Job::new("failing job", Duration::from_secs(60), |_| async {
+        anyhow::bail!("upstream is down")
+    })
+    .schedule(&app.supervisor);
+    wait_until(|| {
+        app.supervisor
+            .status("failing job")
+            .is_some_and(|status| status.last_error.is_some())
+    })
+    .await;
+
+    let (status, ready) = app.get_json("atp.fyi", "/_ready").await;
+    assert_eq!(status, StatusCode::OK);
+    let job = &ready["services"]["failing job"];
+
+    assert_eq!(job["last_error"]["message"], "upstream is down");
+
+    app.stop().await;
+}
+
+#[tokio::test]
+async fn budget_limits_concurrency() {
+    let running = Arc::new(AtomicUsize::new(0));
+    let max_running = Arc::new(AtomicUsize::new(0));
+
+    let (job_running, job_max_running) = (running.clone(), max_running.clone());
+    let job = Job::new("concurrent job", Duration::from_secs(60), move |budget| {
+        let (running, max_running) = (job_running.clone(), job_max_running.clone());
+        async move {
+            budget
+                .run_all((0..6).map(|i| {
+                    let (running, max_running) = (running.clone(), max_running.clone());
+                    async move {
+                        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                        max_running.fetch_max(now, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(20)).await;
+                        running.fetch_sub(1, Ordering::SeqCst);
+                        anyhow::ensure!(i != 3, "task {i} failed");
+                        Ok(())
+                    }
+                }))
+                .await
+        }
+    })
+    .concurrency(2);
+
+    // Every task runs, even though one of them fails.
+    let e = job.run_once().await.expect_err("a task failed");
+    assert_eq!(format!("{e:#}"), "1 of 6 tasks failed: task 3 failed");
+    assert_eq!(max_running.load(Ordering::SeqCst), 2);
+}
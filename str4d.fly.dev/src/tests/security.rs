@@ -0,0 +1,95 @@
+use axum::{
+    Router,
+    http::{
+        StatusCode,
+        header::{
+            CONTENT_SECURITY_POLICY, REFERRER_POLICY, STRICT_TRANSPORT_SECURITY,
+            X_CONTENT_TYPE_OPTIONS,
+        },
+    },
+};
+
+use super::{MockUpstream, TestApp};
+use crate::util::{
+    Multiplexer,
+    hosts::{HostsConfig, Routers},
+};
+
+const DENY_ALL: &str =
+    "default-src 'none'; base-uri 'none'; form-action 'none'; frame-ancestors 'none'";
+
+#[tokio::test]
+async fn pages_have_security_headers() {
+    let app = TestApp::new(&MockUpstream::new().start().await);
+
+    let res = app.get_with("siso.dev", "/", &[]).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let headers = res.headers();
+    assert_eq!(headers[STRICT_TRANSPORT_SECURITY], "max-age=31536000");
+    assert_eq!(headers[X_CONTENT_TYPE_OPTIONS], "nosniff");
+    assert_eq!(headers[REFERRER_POLICY], "strict-origin-when-cross-origin");
+    // siso.dev shows user-generated content, so must not allow any scripts.
+    assert_eq!(
+        headers[CONTENT_SECURITY_POLICY],
+        format!("{DENY_ALL}; style-src 'unsafe-inline'; img-src https://cdn.bsky.app"),
+    );
+}
+
+#[tokio::test]
+async fn policies_are_per_host() {
+    let app = TestApp::new(&MockUpstream::new().start().await);
+
+    let res = app.get_with("go.rfc.observer", "/", &[]).await;
+    assert_eq!(
+        res.headers()[CONTENT_SECURITY_POLICY],
+        format!(
+            "{DENY_ALL}; script-src 'self' 'unsafe-inline'; \
+             style-src 'unsafe-inline'; img-src https://www.svgrepo.com; connect-src 'self'"
+        ),
+    );
+
+    // Error pages are served with the host's policy.
+    let res = app.get_with("blog.jackgrigg.com", "/missing", &[]).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    assert_eq!(
+        res.headers()[CONTENT_SECURITY_POLICY],
+        format!("{DENY_ALL}; style-src 'unsafe-inline'"),
+    );
+    assert_eq!(res.headers()[STRICT_TRANSPORT_SECURITY], "max-age=31536000");
+}
+
+#[tokio::test]
+async fn alias_redirects_have_security_headers() {
+    let app = TestApp::new(&MockUpstream::new().start().await);
+
+    let res = app.get_with("www.atp.fyi", "/", &[]).await;
+    assert_eq!(res.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(res.headers()[STRICT_TRANSPORT_SECURITY], "max-age=31536000");
+    assert_eq!(res.headers()[CONTENT_SECURITY_POLICY], DENY_ALL);
+}
+
+#[test]
+fn invalid_sources_are_rejected() {
+    for source in [
+        "",
+        "https://a.example; script-src *",
+        "'self' https://a.example",
+    ] {
+        let config: HostsConfig = toml::from_str(&format!(
+            "[[host]]\nname = \"example.com\"\nrouter = \"example\"\n\n\
+             [host.csp]\nimg-src = [{source:?}]\n",
+        ))
+        .expect("valid TOML");
+        let routers = Routers::new().register("example", Router::new());
+
+        let Err(e) = Multiplexer::from_config(config, routers) else {
+            panic!("{source:?} is not a valid source");
+        };
+        assert_eq!(
+            e.to_string(),
+            format!(
+                "Content security policy source {source:?} for host example.com is not a single source expression",
+            ),
+        );
+    }
+}
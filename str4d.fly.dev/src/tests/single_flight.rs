@@ -0,0 +1,73 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+use std::time::Duration;
+
+use super::wait_until;
+use crate::util::single_flight::SingleFlight;
+
+/// Returns a `SingleFlight` that lives for the rest of the test run, so that it can be
+/// shared with spawned tasks.
+fn flights() -> &'static SingleFlight<&'static str, usize> {
+    Box::leak(Box::new(SingleFlight::new()))
+}
+
+#[tokio::test]
+async fn panicked_computations_are_rerun() {
+    let flights = flights();
+
+    let panicked = tokio::spawn(flights.run("key", || async { panic!("computation failed") }));
+    assert!(panicked.await.expect_err("panicked").is_panic());
+    assert_eq!(flights.len(), 0);
+
+    assert_eq!(flights.run("key", || async { 2 }).await, 2);
+}
+
+#[tokio::test]
+async fn cancelled_computations_are_rerun() {
+    let flights = flights();
+
+    let started = Arc::new(AtomicUsize::new(0));
+    let computation_started = started.clone();
+    let cancelled = tokio::spawn(flights.run("key", move || async move {
+        computation_started.fetch_add(1, Ordering::SeqCst);
+        std::future::pending().await
+    }));
+    wait_until(|| started.load(Ordering::SeqCst) == 1).await;
+    cancelled.abort();
+    assert!(cancelled.await.expect_err("aborted").is_cancelled());
+    assert_eq!(flights.len(), 0);
+
+    assert_eq!(flights.run("key", || async { 2 }).await, 2);
+}
+
+#[tokio::test]
+async fn waiters_take_over_cancelled_computations() {
+    let flights = flights();
+
+    let started = Arc::new(AtomicUsize::new(0));
+    let computation_started = started.clone();
+    let cancelled = tokio::spawn(flights.run("key", move || async move {
+        computation_started.fetch_add(1, Ordering::SeqCst);
+        std::future::pending().await
+    }));
+    wait_until(|| started.load(Ordering::SeqCst) == 1).await;
+
+    let waiter = tokio::spawn(flights.run("key", || async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        2
+    }));
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    cancelled.abort();
+
+    // Callers arriving after the cancellation wait for the waiter's computation, rather
+    // than starting their own.
+    let later = flights.run("key", || async { 3 }).await;
+    assert_eq!(waiter.await.expect("no panic"), 2);
+    assert_eq!(later, 2);
+
+    // Once it has finished, later callers compute a new value.
+    assert_eq!(flights.len(), 0);
+    assert_eq!(flights.run("key", || async { 4 }).await, 4);
+}
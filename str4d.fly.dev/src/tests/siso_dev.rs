@@ -0,0 +1,36 @@
+use axum::{http::StatusCode, routing::get};
+
+use super::{MockUpstream, TestApp, json};
+
+#[tokio::test]
+async fn feed() {
+    let upstream = MockUpstream::new()
+        .route(
+            "/pds/xrpc/com.atproto.repo.listRecords",
+            get(|| async { json("bluesky/listRecords.json") }),
+        )
+        .start()
+        .await;
+    let app = TestApp::new(&upstream);
+
+    let (status, body) = app.get("siso.dev", "/").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("<p>Hello from the mock PDS!</p><p>Second paragraph.</p>"));
+    assert!(body.contains("A post without images."));
+    // Images are served from the configured CDN.
+    assert!(body.contains(&format!(
+        "{upstream}/cdn/img/feed_thumbnail/plain/did:plc:mzwculbn44rdeouyzjp4y6gx/bafkreigxxxkul4e5rjz4fomqgn6ieeoxbcqeztmxjbrhnbpe7r44ya4ahe@jpeg",
+    )));
+    assert!(body.contains("A mock image"));
+}
+
+#[tokio::test]
+async fn feed_upstream_failure() {
+    let app = TestApp::new(&MockUpstream::new().start().await);
+
+    // The rest of the page is still served.
+    let (status, body) = app.get("siso.dev", "/").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("<title>SISO</title>"));
+    assert!(!body.contains("class=\"post\""));
+}
@@ -0,0 +1,70 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+
+use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+use super::{MockUpstream, TestApp, wait_until};
+use crate::util::supervisor::{SERVICE_RESTARTS, ServiceState};
+
+#[tokio::test]
+async fn failed_services_are_restarted() {
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+    let _guard = metrics::set_default_local_recorder(&recorder);
+    let app = TestApp::new(&MockUpstream::new().start().await);
+
+    // Fails the first time it runs, and then works until shutdown.
+    let runs = Arc::new(AtomicUsize::new(0));
+    let service_runs = runs.clone();
+    app.supervisor.supervise("flaky service", move |handle| {
+        let run = service_runs.fetch_add(1, Ordering::SeqCst);
+        async move {
+            if run == 0 {
+                anyhow::bail!("upstream is down");
+            }
+            handle.report_success();
+            handle.cancelled().await;
+            Ok(())
+        }
+    });
+
+    wait_until(|| {
+        app.supervisor
+            .status("flaky service")
+            .is_some_and(|status| status.restarts == 1)
+    })
+    .await;
+    let status = app.supervisor.status("flaky service").expect("supervised");
+    assert!(matches!(status.state, ServiceState::Backoff { .. }));
+    assert_eq!(
+        status.last_error.expect("failed").message,
+        "upstream is down",
+    );
+    assert!(status.last_success.is_none());
+    let restarts = snapshotter
+        .snapshot()
+        .into_vec()
+        .into_iter()
+        .find_map(|(key, _, _, value)| match value {
+            DebugValue::Counter(count) if key.key().name() == SERVICE_RESTARTS => Some(count),
+            _ => None,
+        });
+    assert_eq!(restarts, Some(1));
+
+    // The service is restarted once its backoff has passed.
+    wait_until(|| {
+        app.supervisor
+            .status("flaky service")
+            .is_some_and(|status| status.state == ServiceState::Running)
+    })
+    .await;
+    assert_eq!(runs.load(Ordering::SeqCst), 2);
+
+    let supervisor = app.supervisor.clone();
+    app.stop().await;
+    let status = supervisor.status("flaky service").expect("supervised");
+    assert_eq!(status.state, ServiceState::Stopped);
+    assert_eq!(status.restarts, 1);
+}
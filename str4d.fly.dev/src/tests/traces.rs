@@ -0,0 +1,128 @@
+use std::net::Ipv4Addr;
+
+use axum::{http::StatusCode, routing::get};
+use opentelemetry::{Value, trace::SpanKind};
+use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider, SpanData};
+use tracing_subscriber::layer::SubscriberExt;
+
+use super::{MockUpstream, TestApp, fly_trusted_proxies, json};
+use crate::util::logging;
+
+/// Returns the value of the given attribute of `span`.
+fn attribute(span: &SpanData, key: &str) -> Option<Value> {
+    span.attributes
+        .iter()
+        .find(|kv| kv.key.as_str() == key)
+        .map(|kv| kv.value.clone())
+}
+
+#[tokio::test]
+async fn requests_are_traced() {
+    let exporter = InMemorySpanExporter::default();
+    let provider = SdkTracerProvider::builder()
+        .with_simple_exporter(exporter.clone())
+        .build();
+    let _guard = tracing::subscriber::set_default(
+        tracing_subscriber::registry().with(logging::otel_layer(&provider)),
+    );
+
+    let upstream = MockUpstream::new()
+        .route(
+            "/pds/xrpc/com.atproto.repo.listRecords",
+            get(|| async { json("bluesky/listRecords.json") }),
+        )
+        .start()
+        .await;
+    let app = TestApp::new(&upstream);
+
+    for _ in 0..2 {
+        let (status, _) = app.get("siso.dev", "/").await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let spans = exporter.get_finished_spans().expect("can get spans");
+    let requests = spans
+        .iter()
+        .filter(|span| span.span_kind == SpanKind::Server)
+        .collect::<Vec<_>>();
+    assert_eq!(requests.len(), 2);
+    for request in &requests {
+        assert_eq!(request.name, "GET /");
+        assert_eq!(attribute(request, "host"), Some("siso.dev".into()));
+        assert_eq!(
+            attribute(request, "http.response.status_code"),
+            Some(200.into()),
+        );
+    }
+
+    // The page is fetched for the first request, and cached for the second.
+    let children_of = |request: &SpanData| {
+        spans
+            .iter()
+            .filter(|span| span.parent_span_id == request.span_context.span_id())
+            .collect::<Vec<_>>()
+    };
+    let hits = requests
+        .iter()
+        .map(|request| {
+            let lookups = children_of(request);
+            assert_eq!(lookups.len(), 1);
+            assert_eq!(lookups[0].name, "cache");
+            assert_eq!(
+                attribute(lookups[0], "cache.name"),
+                Some("siso.dev-feed".into()),
+            );
+            attribute(lookups[0], "cache.hit")
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(hits, [Some(false.into()), Some(true.into())]);
+
+    // Only the first request reached the upstream, from within its trace.
+    let upstreams = spans
+        .iter()
+        .filter(|span| span.span_kind == SpanKind::Client)
+        .collect::<Vec<_>>();
+    assert_eq!(upstreams.len(), 1);
+    assert_eq!(upstreams[0].name, "GET bluesky_pds");
+    assert_eq!(
+        upstreams[0].span_context.trace_id(),
+        requests[0].span_context.trace_id(),
+    );
+    assert_eq!(
+        attribute(upstreams[0], "http.response.status_code"),
+        Some(200.into()),
+    );
+}
+
+#[tokio::test]
+async fn requests_are_traced_with_the_client_behind_the_proxy() {
+    let exporter = InMemorySpanExporter::default();
+    let provider = SdkTracerProvider::builder()
+        .with_simple_exporter(exporter.clone())
+        .build();
+    let _guard = tracing::subscriber::set_default(
+        tracing_subscriber::registry().with(logging::otel_layer(&provider)),
+    );
+
+    let app = TestApp::with_config(&MockUpstream::new().start().await, |config| {
+        config.trusted_proxies = fly_trusted_proxies();
+    });
+    let res = app
+        .get_via_proxy(
+            "fdaa:0:1::2".parse().unwrap(),
+            Ipv4Addr::new(192, 0, 2, 1),
+            "rfc.observer",
+            "/",
+        )
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    // The request's span ends once the response has been sent.
+    drop(res);
+
+    let spans = exporter.get_finished_spans().expect("can get spans");
+    let request = spans
+        .iter()
+        .find(|span| span.span_kind == SpanKind::Server)
+        .expect("request is traced");
+    assert_eq!(attribute(request, "client_ip"), Some("192.0.2.1".into()));
+}
@@ -1,22 +1,46 @@
 use std::{
-    collections::HashMap,
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
     convert::Infallible,
-    env,
     future::Future,
     pin::Pin,
     task::{Context, Poll},
+    time::Instant,
 };
 
 use axum::{
     Router,
-    extract::Request,
+    extract::{FromRequestParts, MatchedPath, Request},
+    http::request::Parts,
+    middleware::Next,
     response::{IntoResponse, Redirect, Response},
     routing::{MethodRouter, Route, future::RouteFuture, get},
 };
-use hyper::{StatusCode, header::HOST};
+use hyper::{
+    Method, StatusCode,
+    header::{HOST, HeaderValue, LOCATION},
+};
+use serde::Deserialize;
 use tower::{Layer, Service};
 
+pub(crate) mod assets;
+pub(crate) mod cache;
+pub(crate) mod client_ip;
+pub(crate) mod compression;
+pub(crate) mod error;
 pub(crate) mod github;
+pub(crate) mod health;
+pub(crate) mod hosts;
+pub(crate) mod http_cache;
+pub(crate) mod limits;
+pub(crate) mod logging;
+pub(crate) mod request_id;
+pub(crate) mod scheduler;
+pub(crate) mod security;
+pub(crate) mod shutdown;
+pub(crate) mod single_flight;
+pub(crate) mod supervisor;
+pub(crate) mod upstream;
 
 fn req_host(req: &Request) -> Option<&str> {
     // RFC 9112 Section 3.2.2:
@@ -37,18 +61,77 @@ fn req_host(req: &Request) -> Option<&str> {
     })
 }
 
+/// Normalizes a host for matching against the hosts handled by a [`Multiplexer`].
+///
+/// This strips any port, drops the trailing dot from fully-qualified domain names, and
+/// lowercases the result.
+fn normalize_host(host: &str) -> String {
+    let host = match host.strip_prefix('[') {
+        // IPv6 literals are bracketed, and may be followed by a port.
+        Some(rest) => rest.split_once(']').map_or(host, |(addr, _)| addr),
+        None => match host.rsplit_once(':') {
+            Some((name, port)) if port.bytes().all(|b| b.is_ascii_digit()) => name,
+            _ => host,
+        },
+    };
+
+    host.strip_suffix('.').unwrap_or(host).to_ascii_lowercase()
+}
+
+/// The part of the request's host that matched the `*` in a wildcard host handled by a
+/// [`Multiplexer`].
+///
+/// For example, a request to `ietf.rfc.observer` that is handled by `*.rfc.observer`
+/// has the subdomain `ietf`.
+#[derive(Clone, Debug)]
+pub(crate) struct Subdomain(pub(crate) String);
+
+impl<S: Send + Sync> FromRequestParts<S> for Subdomain {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        // Only present for requests that were matched by a wildcard host.
+        parts
+            .extensions
+            .get::<Subdomain>()
+            .cloned()
+            .ok_or(StatusCode::NOT_FOUND)
+    }
+}
+
+/// The host that a request was routed to by a [`Multiplexer`], as it was configured.
+///
+/// Unlike the request's own host, this can only take a bounded set of values: requests
+/// handled by a wildcard host have the wildcard (e.g. `*.rfc.observer`), and requests
+/// for [reserved paths](Multiplexer::reserve) have `*`. Requests for unknown hosts don't
+/// have a `MatchedHost`.
+#[derive(Clone, Debug)]
+pub(crate) struct MatchedHost(pub(crate) String);
+
 /// A multiplexer that enables a single server to serve multiple hosts with independent
 /// [`Router`]s.
 pub(crate) struct Multiplexer<S> {
-    routers: HashMap<&'static str, Router<S>>,
+    /// Paths that are handled the same way for every host.
+    reserved_paths: HashSet<&'static str>,
+    reserved: Router<S>,
+    routers: HashMap<String, Router<S>>,
+    /// Routers for wildcard hosts, keyed by the suffix that follows the `*`, and ordered
+    /// from most to least specific.
+    wildcards: Vec<(String, Router<S>)>,
     fallback: Router<S>,
+    /// If set, every request is handled as if it were for this host.
+    test_host: Option<String>,
 }
 
 impl<S> Clone for Multiplexer<S> {
     fn clone(&self) -> Self {
         Self {
+            reserved_paths: self.reserved_paths.clone(),
+            reserved: self.reserved.clone(),
             routers: self.routers.clone(),
+            wildcards: self.wildcards.clone(),
             fallback: self.fallback.clone(),
+            test_host: self.test_host.clone(),
         }
     }
 }
@@ -63,59 +146,56 @@ where
     /// to all requests.
     pub(crate) fn new() -> Self {
         Self {
+            reserved_paths: HashSet::new(),
+            reserved: Router::new(),
             routers: HashMap::new(),
-            fallback: Router::new().fallback(|| async { StatusCode::MISDIRECTED_REQUEST }),
+            wildcards: vec![],
+            fallback: error::DEFAULT.apply(
+                Router::new()
+                    .fallback(|| async { error::Error::new(StatusCode::MISDIRECTED_REQUEST) }),
+            ),
+            test_host: None,
         }
     }
 
     /// Handles requests for the given host by directing them to the given router.
     ///
-    /// The `aliases` are added as temporary (HTTP 307) redirects to the host.
-    pub(crate) fn add(
-        self,
-        host: &'static str,
-        aliases: impl IntoIterator<Item = &'static str>,
-        router: Router<S>,
-    ) -> Self {
-        aliases
-            .into_iter()
-            .fold(self.handle(host, router), |this, alias| {
-                this.redirect_temporary(alias, host)
-            })
-    }
-
-    /// Handles requests for the given host by directing them to the given router.
-    pub(crate) fn handle(mut self, host: &'static str, router: Router<S>) -> Self {
-        self.routers.insert(host, router);
+    /// If `host` is of the form `*.<suffix>`, the router handles requests for every
+    /// subdomain of `<suffix>` that is not handled by a more specific host. The router
+    /// can obtain the matched subdomain with the [`Subdomain`] extractor.
+    pub(crate) fn handle(mut self, host: impl Into<String>, router: Router<S>) -> Self {
+        let host = normalize_host(&host.into());
+        match host.strip_prefix('*') {
+            Some(suffix) => {
+                self.wildcards.retain(|(s, _)| s != suffix);
+                self.wildcards.push((suffix.into(), router));
+                // Longer suffixes are more specific.
+                self.wildcards
+                    .sort_by_key(|(suffix, _)| Reverse(suffix.len()));
+            }
+            None => {
+                self.routers.insert(host, router);
+            }
+        }
         self
     }
 
-    /// Adds a temporary (HTTP 307) redirect between two hosts.
+    /// Handles requests for the given path on every host (including unknown hosts) with
+    /// the given method router.
     ///
-    /// Requests with host `<from>` will be redirected to `https://<to><path_and_query>`.
-    pub(crate) fn redirect_temporary(self, from: &'static str, to: &'static str) -> Self {
-        self.redirect_inner(from, to, Redirect::temporary)
+    /// Reserved paths take precedence over the routes of any host.
+    pub(crate) fn reserve(mut self, path: &'static str, method_router: MethodRouter<S>) -> Self {
+        self.reserved_paths.insert(path);
+        self.reserved = self.reserved.route(path, method_router);
+        self
     }
 
-    /// Adds a redirect between two hosts.
+    /// Handles every request as if it were for `host`, ignoring the request's own host.
     ///
-    /// Requests with host `<from>` will be redirected to `https://<to><path_and_query>`.
-    fn redirect_inner<F>(self, from: &'static str, to: &'static str, redirect: F) -> Self
-    where
-        F: FnOnce(&str) -> Redirect + Sync,
-        F: Clone + Send + 'static,
-    {
-        self.handle(
-            from,
-            Router::new().fallback(move |req: Request| async move {
-                let to_uri = format!(
-                    "https://{}{}",
-                    to,
-                    req.uri().path_and_query().map(|p| p.as_str()).unwrap_or(""),
-                );
-                redirect(&to_uri)
-            }),
-        )
+    /// This is useful for testing a site locally.
+    pub(crate) fn test_host(mut self, host: Option<String>) -> Self {
+        self.test_host = host.as_deref().map(normalize_host);
+        self
     }
 
     /// Applies a [`tower::Layer`] to all routers in the multiplexer.
@@ -138,14 +218,41 @@ where
             .into_iter()
             .map(|(host, router)| (host, router.layer(layer.clone())))
             .collect();
+        let wildcards = self
+            .wildcards
+            .into_iter()
+            .map(|(suffix, router)| (suffix, router.layer(layer.clone())))
+            .collect();
 
         Multiplexer {
+            reserved_paths: self.reserved_paths,
+            reserved: self.reserved.layer(layer.clone()),
             routers,
+            wildcards,
             fallback: self.fallback.layer(layer),
+            test_host: self.test_host,
         }
     }
 }
 
+/// Returns a router that redirects every request to the same path and query on `to`,
+/// with a redirect of the given kind.
+///
+/// Requests will be redirected to `https://<to><path_and_query>`.
+fn host_redirect<S>(to: String, kind: RedirectKind) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new().fallback(move |req: Request| async move {
+        let to_uri = format!(
+            "https://{}{}",
+            to,
+            req.uri().path_and_query().map(|p| p.as_str()).unwrap_or(""),
+        );
+        kind.to(&to_uri)
+    })
+}
+
 impl Service<Request> for Multiplexer<()> {
     type Response = Response;
     type Error = Infallible;
@@ -158,20 +265,70 @@ impl Service<Request> for Multiplexer<()> {
     }
 
     #[inline]
-    fn call(&mut self, req: Request) -> Self::Future {
-        if let Some(router) = env::var("TEST_HOST")
-            .ok()
-            .as_deref()
-            .or_else(|| req_host(&req))
-            .and_then(|s| self.routers.get_mut(s))
-        {
-            router.call(req)
-        } else {
-            self.fallback.call(req)
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        if self.reserved_paths.contains(req.uri().path()) {
+            req.extensions_mut().insert(MatchedHost("*".into()));
+            return self.reserved.call(req);
+        }
+
+        let Some(host) = self
+            .test_host
+            .clone()
+            .or_else(|| req_host(&req).map(normalize_host))
+        else {
+            return self.fallback.call(req);
+        };
+
+        if let Some(router) = self.routers.get_mut(&host) {
+            req.extensions_mut().insert(MatchedHost(host));
+            return router.call(req);
         }
+
+        for (suffix, router) in &mut self.wildcards {
+            if let Some(subdomain) = host.strip_suffix(suffix.as_str())
+                && !subdomain.is_empty()
+            {
+                req.extensions_mut().insert(Subdomain(subdomain.into()));
+                req.extensions_mut()
+                    .insert(MatchedHost(format!("*{suffix}")));
+                return router.call(req);
+            }
+        }
+
+        self.fallback.call(req)
     }
 }
 
+/// The name of the counter of HTTP requests that we have handled.
+pub(crate) const HTTP_REQUESTS: &str = "http.requests.total";
+
+/// The name of the histogram of how long we take to respond to HTTP requests.
+pub(crate) const HTTP_REQUEST_DURATION: &str = "http.request.duration.seconds";
+
+/// The buckets (in seconds) for [`HTTP_REQUEST_DURATION`].
+///
+/// Most pages are served from a cache, but a cache miss can involve several upstream
+/// requests.
+pub(crate) const HTTP_REQUEST_DURATION_BUCKETS: &[f64] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// The label used for requests that weren't matched by a host or route.
+const UNMATCHED: &str = "unmatched";
+
+/// A [`Layer`] that records metrics for every request.
+///
+/// Requests are labelled with:
+/// - `host`: the [`MatchedHost`], or `unmatched`.
+/// - `path`: the [`MatchedPath`] (the route template, e.g. `/{acronym}`), or `unmatched`
+///   for requests handled by a fallback. Routers used as a fallback service need to
+///   [expose their matched path](expose_matched_path).
+/// - `method`: the request method, or `OTHER` for non-standard methods.
+/// - `status`: the class of the response status (e.g. `2xx`), or `error` if the inner
+///   service failed.
+///
+/// This needs to be applied to each [`Router`] (via [`Multiplexer::layer`]) rather than
+/// to the [`Multiplexer`] as a whole, so that it can see the matched route.
 #[derive(Clone)]
 pub(crate) struct MetricsLayer {}
 
@@ -208,35 +365,138 @@ where
     }
 
     fn call(&mut self, req: Request) -> Self::Future {
-        let host = req_host(&req);
-        let handler = format!(
-            "{}{}",
-            host.unwrap_or_default(),
-            req.uri()
-                .path_and_query()
-                .map(|p| p.as_str())
-                .unwrap_or("/")
-        );
+        let host = req
+            .extensions()
+            .get::<MatchedHost>()
+            .map_or(UNMATCHED.into(), |host| host.0.clone());
+        let path = req
+            .extensions()
+            .get::<MatchedPath>()
+            .map_or(UNMATCHED, |path| path.as_str())
+            .to_owned();
+        let method = method_label(req.method());
         let mut inner = self.inner.clone();
 
         Box::pin(async move {
+            let start = Instant::now();
             let res = inner.call(req).await;
-            if let Ok(response) = &res {
-                // Only collect metrics for requests we expected to handle.
-                let status = response.status();
-                if status.is_success() || status.is_redirection() {
-                    metrics::counter!("http.requests.total", "handler" => handler).increment(1);
-                }
-            }
+            let elapsed = start.elapsed();
+
+            let (path, status) = match &res {
+                Ok(response) => (
+                    response
+                        .extensions()
+                        .get::<MatchedPath>()
+                        .map_or(path, |path| path.as_str().to_owned()),
+                    status_class(response.status()),
+                ),
+                Err(_) => (path, "error"),
+            };
+            let labels = [
+                ("host", host),
+                ("path", path),
+                ("method", method.to_owned()),
+                ("status", status.to_owned()),
+            ];
+            metrics::counter!(HTTP_REQUESTS, &labels).increment(1);
+            metrics::histogram!(HTTP_REQUEST_DURATION, &labels).record(elapsed.as_secs_f64());
+
             res
         })
     }
 }
 
-pub(crate) fn get_temp_redir<S>(uri: &str) -> MethodRouter<S>
+/// Copies the [`MatchedPath`] of a request (if any) into the extensions of its response.
+///
+/// A [`Router`] that is used as a fallback service matches its routes after the outer
+/// router's layers have seen the request, so this lets [`MetricsLayer`] see which of its
+/// routes handled the request.
+pub(crate) async fn expose_matched_path(req: Request, next: Next) -> Response {
+    let path = req.extensions().get::<MatchedPath>().cloned();
+    let mut res = next.run(req).await;
+    if let Some(path) = path {
+        res.extensions_mut().insert(path);
+    }
+    res
+}
+
+/// Returns the label for the given request method.
+///
+/// Clients can send arbitrary methods, so we only label the standard ones.
+fn method_label(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::HEAD => "HEAD",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::DELETE => "DELETE",
+        Method::CONNECT => "CONNECT",
+        Method::OPTIONS => "OPTIONS",
+        Method::TRACE => "TRACE",
+        Method::PATCH => "PATCH",
+        _ => "OTHER",
+    }
+}
+
+/// Returns the class of the given status (e.g. `2xx`).
+fn status_class(status: StatusCode) -> &'static str {
+    match status.as_u16() {
+        100..=199 => "1xx",
+        200..=299 => "2xx",
+        300..=399 => "3xx",
+        400..=499 => "4xx",
+        _ => "5xx",
+    }
+}
+
+/// The kinds of redirect we can issue.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum RedirectKind {
+    /// HTTP 307 Temporary Redirect.
+    #[default]
+    Temporary,
+    /// HTTP 308 Permanent Redirect.
+    Permanent,
+    /// HTTP 301 Moved Permanently.
+    ///
+    /// Unlike [`RedirectKind::Permanent`], clients may change the request method to
+    /// `GET`. This is the most widely understood permanent redirect, so prefer it for
+    /// pages that search engines should forget about.
+    MovedPermanently,
+}
+
+impl RedirectKind {
+    /// Returns a response redirecting to `uri`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `uri` isn't a valid header value.
+    pub(crate) fn to(self, uri: &str) -> Response {
+        match self {
+            RedirectKind::Temporary => Redirect::temporary(uri).into_response(),
+            RedirectKind::Permanent => Redirect::permanent(uri).into_response(),
+            RedirectKind::MovedPermanently => (
+                StatusCode::MOVED_PERMANENTLY,
+                [(LOCATION, HeaderValue::try_from(uri).expect("valid"))],
+            )
+                .into_response(),
+        }
+    }
+}
+
+pub(crate) fn get_redir<S>(kind: RedirectKind, uri: &str) -> MethodRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    let uri = uri.to_owned();
+    get(move || async move { kind.to(&uri) })
+}
+
+/// Responds with HTTP 410 Gone, for paths that have been deliberately retired.
+pub(crate) fn get_gone<S>() -> MethodRouter<S>
 where
     S: Clone + Send + Sync + 'static,
 {
-    let redir = Redirect::temporary(uri);
-    get(|| async { redir })
+    get(|| async { StatusCode::GONE })
 }
@@ -0,0 +1,119 @@
+//! Static assets served from `/static/` on every host.
+//!
+//! Assets are embedded in the binary, and served under a filename that includes a hash
+//! of their contents, so that browsers can cache them forever. Templates refer to them
+//! by name with [`url`]:
+//!
+//! ```html
+//! <script src="{{ crate::util::assets::url("chart.umd.js") }}"></script>
+//! ```
+//!
+//! Our assets are currently all third-party scripts, pinned to specific versions (and
+//! checked against the hashes in `res/static/SHA256SUMS`) by `res/static/fetch.sh`,
+//! which needs to be run before building. The build script checks them against those
+//! hashes again, and fails if any are missing.
+
+use std::{collections::HashMap, sync::LazyLock};
+
+use axum::{
+    Router,
+    extract::Path,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use hyper::{StatusCode, header::CONTENT_TYPE};
+use sha2::{Digest, Sha256};
+
+use super::http_cache::CachePolicy;
+
+/// The assets that we serve, by name.
+pub(crate) const ASSETS: &[(&str, &[u8])] = &[
+    (
+        "chart.umd.js",
+        include_bytes!("../../res/static/chart.umd.js"),
+    ),
+    (
+        "chartjs-adapter-luxon.umd.min.js",
+        include_bytes!("../../res/static/chartjs-adapter-luxon.umd.min.js"),
+    ),
+    (
+        "graphology-library.min.js",
+        include_bytes!("../../res/static/graphology-library.min.js"),
+    ),
+    (
+        "graphology.umd.min.js",
+        include_bytes!("../../res/static/graphology.umd.min.js"),
+    ),
+    (
+        "luxon.min.js",
+        include_bytes!("../../res/static/luxon.min.js"),
+    ),
+    (
+        "sigma.min.js",
+        include_bytes!("../../res/static/sigma.min.js"),
+    ),
+];
+
+static INDEX: LazyLock<Index> = LazyLock::new(Index::new);
+
+struct Index {
+    /// The content-hashed filename of each asset, keyed by name.
+    filenames: HashMap<&'static str, String>,
+    /// The contents of each asset, keyed by content-hashed filename.
+    contents: HashMap<String, (&'static str, &'static [u8])>,
+}
+
+impl Index {
+    fn new() -> Self {
+        let mut filenames = HashMap::new();
+        let mut contents = HashMap::new();
+
+        for &(name, data) in ASSETS {
+            let (stem, extension) = name.rsplit_once('.').expect("assets have extensions");
+            let content_type = match extension {
+                "css" => "text/css",
+                "js" => "text/javascript",
+                _ => panic!("Unknown content type for asset {name}"),
+            };
+
+            let hash = format!("{:x}", Sha256::digest(data));
+            let filename = format!("{stem}.{}.{extension}", &hash[..16]);
+            filenames.insert(name, filename.clone());
+            contents.insert(filename, (content_type, data));
+        }
+
+        Self {
+            filenames,
+            contents,
+        }
+    }
+}
+
+/// Returns the URL of the asset with the given name.
+///
+/// Panics if there is no such asset.
+pub(crate) fn url(name: &str) -> String {
+    match INDEX.filenames.get(name) {
+        Some(filename) => format!("/static/{filename}"),
+        None => panic!("Unknown asset {name}"),
+    }
+}
+
+/// Returns a router that serves our assets.
+pub(crate) fn router<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new().route(
+        "/static/{filename}",
+        get(serve).layer(CachePolicy::immutable()),
+    )
+}
+
+async fn serve(Path(filename): Path<String>) -> Response {
+    match INDEX.contents.get(&filename) {
+        Some(&(content_type, data)) => ([(CONTENT_TYPE, content_type)], data).into_response(),
+        // Includes outdated versions of assets, which pages we serve no longer refer to.
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
@@ -0,0 +1,486 @@
+//! Caches for data that is expensive to fetch from upstreams.
+//!
+//! Some of our data (like the IETF documents for a group, or the atp.fyi network map)
+//! takes minutes to fetch, so losing it on every deploy or restart hurts. A [`Cache`]
+//! keeps values in memory, and also persists them to disk (if `cache.dir` is configured;
+//! see [`crate::config::Cache`]) so that they survive restarts:
+//!
+//! ```text
+//! <dir>/<name>/v<version>/<hash of key>.json
+//! ```
+//!
+//! Values are refreshed once they are older than the cache's TTL, whether they were
+//! fetched by this process or loaded from disk. Until a refresh succeeds, we keep
+//! serving the last value we fetched (stale-while-revalidate): a refresh happens in the
+//! background, and a failed refresh never replaces a value. [`AgedJson`] lets clients
+//! know how old the data they are given is.
+//!
+//! Values can also be kept warm by a scheduled job (see [`super::scheduler`]) calling
+//! [`Cache::warm`]. Once a key is being warmed, reads of it never trigger refreshes;
+//! they are left to the job.
+//!
+//! Handlers only ever read what is already cached, with [`Cache::read`], so that no
+//! request waits on an upstream. A key that hasn't been fetched yet is fetched in the
+//! background, and until then the handler responds with [`Warming`].
+//!
+//! Concurrent fetches of the same key (whether inline or in the background) are coalesced
+//! into one; see [`SingleFlight`].
+//!
+//! Each cache has a version that must be bumped whenever the schema of its values
+//! changes, so that entries written by older versions of the server are ignored (and
+//! removed).
+//!
+//! Lookups in a [`Cache`] get a debug-level `cache` span recording whether they were
+//! hits, so that they show up in traces (see [`super::logging`]).
+
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    Json,
+    response::{IntoResponse, Response},
+};
+use hyper::{
+    StatusCode,
+    header::{ETAG, HeaderName, HeaderValue, LAST_MODIFIED, RETRY_AFTER},
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use sha2::{Digest, Sha256};
+use tokio::sync::OnceCell;
+use tracing::{Instrument, Span, field::Empty};
+
+use super::{error, http_cache::etag, single_flight::SingleFlight, supervisor::Supervisor};
+
+/// The header that tells clients how old the data in an [`AgedJson`] response is, in
+/// seconds.
+pub(crate) const DATA_AGE: HeaderName = HeaderName::from_static("x-data-age");
+
+/// A cache of values that are fetched from upstreams, keyed by strings.
+///
+/// Failures to fetch a value (of type `E`) are not cached, but are shared with every
+/// caller that was waiting for the same fetch.
+pub(crate) struct Cache<V, E> {
+    /// The name of the cache, which is also the name of its directory on disk.
+    name: &'static str,
+    /// The version of the schema of `V`.
+    version: u32,
+    /// How long values are fresh for.
+    ttl: Duration,
+    entries: Mutex<HashMap<String, Cached<V>>>,
+    /// Fetches that are in progress.
+    fetching: SingleFlight<String, Result<Cached<V>, E>>,
+    /// Keys that are being fetched in the background.
+    refreshing: Mutex<HashSet<String>>,
+    /// Keys that are kept warm by a scheduled job.
+    warmed: Mutex<HashSet<String>>,
+    /// Set once we have removed entries persisted by older versions.
+    pruned: OnceCell<()>,
+}
+
+/// A value from a [`Cache`], along with when it was fetched.
+#[derive(Clone)]
+pub(crate) struct Cached<V> {
+    pub(crate) value: V,
+    stored_at: SystemTime,
+}
+
+impl<V> Cached<V> {
+    /// Returns how long ago the value was fetched.
+    pub(crate) fn age(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(self.stored_at)
+            .unwrap_or_default()
+    }
+
+    fn is_fresh(&self, ttl: Duration) -> bool {
+        self.age() < ttl
+    }
+
+    /// Derives a value from this one, keeping its age.
+    pub(crate) fn map<U>(self, f: impl FnOnce(V) -> U) -> Cached<U> {
+        Cached {
+            value: f(self.value),
+            stored_at: self.stored_at,
+        }
+    }
+}
+
+/// Marks a key as being refreshed in the background until dropped, so that the key can
+/// be refreshed again even if the refresh panics or is cancelled.
+struct Refreshing {
+    keys: &'static Mutex<HashSet<String>>,
+    key: String,
+}
+
+impl Refreshing {
+    /// Returns `None` if `key` is already being refreshed.
+    fn start(keys: &'static Mutex<HashSet<String>>, key: String) -> Option<Self> {
+        let started = keys.lock().expect("not poisoned").insert(key.clone());
+        started.then(|| Self { keys, key })
+    }
+}
+
+impl Drop for Refreshing {
+    fn drop(&mut self) {
+        self.keys.lock().expect("not poisoned").remove(&self.key);
+    }
+}
+
+/// An entry as it is persisted to disk.
+#[derive(Deserialize, Serialize)]
+struct Persisted<K, V> {
+    key: K,
+    /// Seconds since the UNIX epoch.
+    stored_at: u64,
+    value: V,
+}
+
+impl<V, E> Cache<V, E>
+where
+    V: Clone + Serialize + DeserializeOwned + Send + Sync + 'static,
+    E: Clone + fmt::Display + Send + Sync + 'static,
+{
+    pub(crate) fn new(name: &'static str, version: u32, ttl: Duration) -> Self {
+        Self {
+            name,
+            version,
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+            fetching: SingleFlight::new(),
+            refreshing: Mutex::new(HashSet::new()),
+            warmed: Mutex::new(HashSet::new()),
+            pruned: OnceCell::new(),
+        }
+    }
+
+    /// Returns the value cached for `key`, fetching it with `fetch` if it isn't cached.
+    ///
+    /// If the cached value has expired, it is returned anyway, and refreshed in the
+    /// background under `supervisor` (unless it is kept warm by [`Cache::warm`]).
+    ///
+    /// If `dir` is set, values are also persisted under it.
+    #[tracing::instrument(
+        level = "debug",
+        name = "cache",
+        skip_all,
+        fields(cache.name = self.name, cache.hit = Empty, cache.stale = Empty),
+    )]
+    pub(crate) async fn get<F>(
+        &'static self,
+        supervisor: &Supervisor,
+        dir: Option<PathBuf>,
+        key: String,
+        fetch: impl FnOnce() -> F + Send + 'static,
+    ) -> Result<Cached<V>, E>
+    where
+        F: Future<Output = Result<V, E>> + Send + 'static,
+    {
+        match self.lookup(dir.as_deref(), &key).await {
+            Some(cached) => {
+                if self.needs_refresh(&key, &cached) {
+                    self.fetch_in_background(supervisor, dir, key, fetch);
+                }
+                Ok(cached)
+            }
+            None => self.fetch(dir.as_deref(), key, fetch).await,
+        }
+    }
+
+    /// Returns the value cached for `key`, without waiting for any fetches.
+    ///
+    /// If nothing is cached for `key`, it is fetched with `fetch` in the background
+    /// under `supervisor`, and `None` is returned. As with [`Cache::get`], an expired
+    /// value is returned anyway, and refreshed in the background (unless it is kept warm
+    /// by [`Cache::warm`]).
+    #[tracing::instrument(
+        level = "debug",
+        name = "cache",
+        skip_all,
+        fields(cache.name = self.name, cache.hit = Empty, cache.stale = Empty),
+    )]
+    pub(crate) async fn read<F>(
+        &'static self,
+        supervisor: &Supervisor,
+        dir: Option<PathBuf>,
+        key: String,
+        fetch: impl FnOnce() -> F + Send + 'static,
+    ) -> Option<Cached<V>>
+    where
+        F: Future<Output = Result<V, E>> + Send + 'static,
+    {
+        let cached = self.lookup(dir.as_deref(), &key).await;
+        if cached
+            .as_ref()
+            .is_none_or(|cached| self.needs_refresh(&key, cached))
+        {
+            self.fetch_in_background(supervisor, dir, key, fetch);
+        }
+        cached
+    }
+
+    /// Fetches the value for `key` and caches it, whether or not it has expired.
+    ///
+    /// This is called by scheduled jobs; from then on, reads of `key` leave refreshing
+    /// it to the job.
+    pub(crate) async fn warm<F>(
+        &self,
+        dir: Option<PathBuf>,
+        key: String,
+        fetch: impl FnOnce() -> F,
+    ) -> Result<Cached<V>, E>
+    where
+        F: Future<Output = Result<V, E>>,
+    {
+        self.warmed
+            .lock()
+            .expect("not poisoned")
+            .insert(key.clone());
+        self.fetch(dir.as_deref(), key, fetch).await
+    }
+
+    /// Returns how long values are fresh for.
+    pub(crate) fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    fn is_warmed(&self, key: &str) -> bool {
+        self.warmed.lock().expect("not poisoned").contains(key)
+    }
+
+    fn get_in_memory(&self, key: &str) -> Option<Cached<V>> {
+        self.entries.lock().expect("not poisoned").get(key).cloned()
+    }
+
+    /// Returns the value cached for `key` in memory or on disk, recording whether there
+    /// was one in the current span.
+    async fn lookup(&self, dir: Option<&Path>, key: &str) -> Option<Cached<V>> {
+        let cached = match (self.get_in_memory(key), dir) {
+            (Some(cached), _) => Some(cached),
+            (None, Some(dir)) => self.load(dir, key).await,
+            (None, None) => None,
+        };
+        Span::current().record("cache.hit", cached.is_some());
+        cached
+    }
+
+    /// Returns whether a read of `cached` should refresh it, recording whether it is
+    /// stale in the current span.
+    fn needs_refresh(&self, key: &str, cached: &Cached<V>) -> bool {
+        let stale = !cached.is_fresh(self.ttl);
+        Span::current().record("cache.stale", stale);
+        stale && !self.is_warmed(key)
+    }
+
+    /// Fetches the value for `key`, and caches it if the fetch succeeds.
+    ///
+    /// If `key` is already being fetched, this instead waits for that fetch.
+    async fn fetch<F>(
+        &self,
+        dir: Option<&Path>,
+        key: String,
+        fetch: impl FnOnce() -> F,
+    ) -> Result<Cached<V>, E>
+    where
+        F: Future<Output = Result<V, E>>,
+    {
+        self.fetching
+            .run(key.clone(), || async move {
+                let cached = Cached {
+                    value: fetch().await?,
+                    stored_at: SystemTime::now(),
+                };
+                if let Some(dir) = dir {
+                    self.store(dir, &key, &cached).await;
+                }
+                self.entries
+                    .lock()
+                    .expect("not poisoned")
+                    .insert(key, cached.clone());
+                Ok(cached)
+            })
+            .await
+    }
+
+    /// Fetches the value for `key` in a task spawned by `supervisor`, unless it is
+    /// already being fetched in the background.
+    fn fetch_in_background<F>(
+        &'static self,
+        supervisor: &Supervisor,
+        dir: Option<PathBuf>,
+        key: String,
+        fetch: impl FnOnce() -> F + Send + 'static,
+    ) where
+        F: Future<Output = Result<V, E>> + Send + 'static,
+    {
+        let Some(refreshing) = Refreshing::start(&self.refreshing, key.clone()) else {
+            return;
+        };
+
+        // The refresh can outlast the request that triggered it, so it gets its own
+        // trace rather than keeping the request's open.
+        let span = tracing::debug_span!(parent: None, "cache refresh", cache.name = self.name);
+        span.follows_from(Span::current());
+        supervisor.spawn(
+            "cache refresh",
+            async move {
+                let _refreshing = refreshing;
+                if let Err(e) = self.fetch(dir.as_deref(), key, fetch).await {
+                    tracing::warn!("Failed to refresh {} cache: {e}", self.name);
+                }
+            }
+            .instrument(span),
+        );
+    }
+
+    fn dir(&self, dir: &Path) -> PathBuf {
+        dir.join(self.name).join(format!("v{}", self.version))
+    }
+
+    fn path(&self, dir: &Path, key: &str) -> PathBuf {
+        self.dir(dir)
+            .join(format!("{:x}.json", Sha256::digest(key.as_bytes())))
+    }
+
+    /// Loads the persisted entry for `key` (however old it is) into memory.
+    async fn load(&self, dir: &Path, key: &str) -> Option<Cached<V>> {
+        self.pruned.get_or_init(|| self.prune(dir)).await;
+
+        let path = self.path(dir, key);
+        let contents = tokio::fs::read_to_string(&path).await.ok()?;
+        let persisted = match serde_json::from_str::<Persisted<String, V>>(&contents) {
+            Ok(persisted) if persisted.key == key => persisted,
+            Ok(_) => return None,
+            Err(e) => {
+                tracing::warn!("Ignoring invalid cache entry {}: {e}", path.display());
+                return None;
+            }
+        };
+
+        let cached = Cached {
+            value: persisted.value,
+            stored_at: UNIX_EPOCH + Duration::from_secs(persisted.stored_at),
+        };
+        let mut entries = self.entries.lock().expect("not poisoned");
+        Some(entries.entry(persisted.key).or_insert(cached).clone())
+    }
+
+    /// Persists the given entry, logging any failure.
+    async fn store(&self, dir: &Path, key: &str, cached: &Cached<V>) {
+        let path = self.path(dir, key);
+        let persisted = Persisted {
+            key,
+            stored_at: cached
+                .stored_at
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            value: &cached.value,
+        };
+        if let Err(e) = write_json(&path, &persisted).await {
+            tracing::warn!("Failed to persist cache entry {}: {e}", path.display());
+        }
+    }
+
+    /// Removes entries persisted by other versions of this cache.
+    async fn prune(&self, dir: &Path) {
+        let current = self.dir(dir);
+        let Ok(mut versions) = tokio::fs::read_dir(dir.join(self.name)).await else {
+            return;
+        };
+        while let Ok(Some(version)) = versions.next_entry().await {
+            let path = version.path();
+            if path != current {
+                tracing::info!("Removing outdated cache entries in {}", path.display());
+                if let Err(e) = tokio::fs::remove_dir_all(&path).await {
+                    tracing::warn!("Failed to remove {}: {e}", path.display());
+                }
+            }
+        }
+    }
+}
+
+/// A JSON response for data from a [`Cache`], or [`Warming`] if there is no data yet.
+///
+/// The age of the data is reported in the [`DATA_AGE`] header, and in a `data_age_secs`
+/// field alongside the data's own fields. The response also has a `Last-Modified` header
+/// (when the data was fetched), and an `ETag` that only depends on the data (not on its
+/// age), so that clients can revalidate it (see [`super::http_cache`]).
+pub(crate) struct AgedJson<T>(pub(crate) Option<Cached<T>>);
+
+impl<T: Serialize> IntoResponse for AgedJson<T> {
+    fn into_response(self) -> Response {
+        #[derive(Serialize)]
+        struct WithAge<'a, T> {
+            #[serde(flatten)]
+            data: &'a T,
+            data_age_secs: u64,
+        }
+
+        match self.0 {
+            Some(cached) => {
+                let age = cached.age().as_secs();
+                let etag = etag(&serde_json::to_vec(&cached.value).unwrap_or_default());
+                let last_modified =
+                    HeaderValue::from_str(&httpdate::fmt_http_date(cached.stored_at))
+                        .expect("valid");
+                (
+                    [
+                        (DATA_AGE, HeaderValue::from(age)),
+                        (ETAG, etag),
+                        (LAST_MODIFIED, last_modified),
+                    ],
+                    Json(WithAge {
+                        data: &cached.value,
+                        data_age_secs: age,
+                    }),
+                )
+                    .into_response()
+            }
+            None => Warming.into_response(),
+        }
+    }
+}
+
+/// The response for data that hasn't been fetched yet (see [`Cache::read`]).
+///
+/// This asks clients to try again once we have (probably) fetched it.
+pub(crate) struct Warming;
+
+impl Warming {
+    const RETRY_AFTER: Duration = Duration::from_secs(30);
+}
+
+impl IntoResponse for Warming {
+    fn into_response(self) -> Response {
+        (
+            [(RETRY_AFTER, HeaderValue::from(Self::RETRY_AFTER.as_secs()))],
+            error::Error::new(StatusCode::SERVICE_UNAVAILABLE)
+                .detail("We are still fetching this data. Please try again shortly."),
+        )
+            .into_response()
+    }
+}
+
+/// Writes `value` to `path` as JSON, replacing any existing file atomically.
+async fn write_json(path: &Path, value: &impl Serialize) -> Result<(), Box<dyn Error>> {
+    write(path, &serde_json::to_vec(value)?).await
+}
+
+/// Writes `contents` to `path`, replacing any existing file atomically, so that a crash
+/// mid-write never leaves a truncated file behind.
+pub(super) async fn write(path: &Path, contents: &[u8]) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    tokio::fs::write(&tmp, contents).await?;
+    tokio::fs::rename(&tmp, path).await?;
+    Ok(())
+}
@@ -0,0 +1,106 @@
+//! The IP addresses of our clients.
+//!
+//! We run behind fly.io's proxy, so the peer of each connection is the proxy rather than
+//! the client. The proxy tells us who the client is with the `Fly-Client-IP` header, and
+//! by appending the peer it saw to `X-Forwarded-For`. Anyone can send these headers, so
+//! we only believe them when the peer is one of our [`TrustedProxies`].
+
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    sync::Arc,
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use hyper::header::HeaderMap;
+use ipnet::IpNet;
+use serde::Deserialize;
+
+const FLY_CLIENT_IP: &str = "fly-client-ip";
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// The networks of the proxies whose client IP headers we believe.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct TrustedProxies(Vec<IpNet>);
+
+impl TrustedProxies {
+    fn contains(&self, addr: &IpAddr) -> bool {
+        self.0.iter().any(|net| net.contains(addr))
+    }
+
+    /// Returns the IP address of the client that sent a request, given the peer that
+    /// connected to us and the request's headers.
+    pub(crate) fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        if !self.contains(&peer) {
+            return peer;
+        }
+
+        if let Some(addr) = headers
+            .get(FLY_CLIENT_IP)
+            .and_then(|value| value.to_str().ok()?.trim().parse().ok())
+        {
+            return addr;
+        }
+
+        // Each proxy appends the peer it saw, so the client is the last address that
+        // wasn't added by (or isn't) one of our proxies.
+        let mut client = peer;
+        for value in headers.get_all(X_FORWARDED_FOR).iter().rev() {
+            let Ok(value) = value.to_str() else { break };
+            for addr in value.rsplit(',') {
+                match addr.trim().parse() {
+                    Ok(addr) => client = addr,
+                    Err(_) => return client,
+                }
+                if !self.contains(&client) {
+                    return client;
+                }
+            }
+        }
+        client
+    }
+}
+
+impl FromStr for TrustedProxies {
+    type Err = ipnet::AddrParseError;
+
+    /// Parses a comma-separated list of networks (e.g. `10.0.0.0/8,fdaa::/16`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(|net| net.trim().parse())
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+/// The IP address of the client that sent a request.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ClientIp(pub(crate) IpAddr);
+
+impl fmt::Display for ClientIp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Determines the [`ClientIp`] of every request that came in over a connection.
+///
+/// Requests that we didn't receive over a connection (like those in tests) don't get a
+/// `ClientIp`.
+pub(crate) async fn resolve(
+    State(proxies): State<Arc<TrustedProxies>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    if let Some(&ConnectInfo(peer)) = req.extensions().get::<ConnectInfo<SocketAddr>>() {
+        let client_ip = ClientIp(proxies.client_ip(peer.ip().to_canonical(), req.headers()));
+        req.extensions_mut().insert(client_ip);
+    }
+    next.run(req).await
+}
@@ -0,0 +1,63 @@
+//! Compression of our responses.
+//!
+//! Pages and JSON datasets (like the atp.fyi network map) compress very well, so we
+//! compress them with whichever of brotli, zstd or gzip the client prefers. We leave
+//! alone responses that are small (compression would barely help), already compressed
+//! (like images), or streamed (we don't know how large they are, and compression would
+//! buffer them).
+
+use axum::body::HttpBody;
+use hyper::{
+    Response,
+    header::{CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE},
+};
+use tower_http::compression::{CompressionLayer, predicate::Predicate};
+
+/// Responses smaller than this aren't worth compressing.
+const MIN_SIZE: u64 = 1024;
+
+/// The content types that we compress.
+const COMPRESSIBLE_TYPES: &[&str] = &[
+    "application/javascript",
+    "application/json",
+    "image/svg+xml",
+    "text/css",
+    "text/html",
+    "text/javascript",
+    "text/plain",
+];
+
+/// Returns a layer that compresses responses, as negotiated with the client.
+pub(crate) fn layer() -> CompressionLayer<Compressible> {
+    CompressionLayer::new().compress_when(Compressible)
+}
+
+/// Decides which responses to compress.
+#[derive(Clone, Copy)]
+pub(crate) struct Compressible;
+
+impl Predicate for Compressible {
+    fn should_compress<B: HttpBody>(&self, response: &Response<B>) -> bool {
+        let headers = response.headers();
+
+        let size = response.body().size_hint().exact().or_else(|| {
+            headers
+                .get(CONTENT_LENGTH)
+                .and_then(|len| len.to_str().ok()?.parse().ok())
+        });
+        let content_type = headers
+            .get(CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .and_then(|content_type| content_type.split(';').next())
+            .map(str::trim);
+
+        // Streamed responses don't have a known size.
+        size.is_some_and(|size| size >= MIN_SIZE)
+            && content_type.is_some_and(|content_type| {
+                COMPRESSIBLE_TYPES
+                    .iter()
+                    .any(|allowed| content_type.eq_ignore_ascii_case(allowed))
+            })
+            && !headers.contains_key(CONTENT_ENCODING)
+    }
+}
@@ -0,0 +1,218 @@
+//! Errors that our handlers respond with.
+//!
+//! Handlers return an [`Error`] instead of a bare status code. Each site renders errors
+//! (along with bare error responses, like axum's 404 for an unknown route) with its
+//! [`ErrorPages`], so that visitors see a page in the site's style that includes the
+//! [`RequestId`]. Requests for API routes (under `/api/`) instead get RFC 9457 problem
+//! details.
+
+use std::sync::{Arc, LazyLock};
+
+use askama::Template;
+use axum::{
+    Json, Router,
+    body::HttpBody,
+    extract::{Request, State},
+    middleware::Next,
+    response::{Html, IntoResponse, Response},
+};
+use hyper::{
+    StatusCode,
+    header::{CONTENT_LENGTH, CONTENT_TYPE, HeaderValue},
+};
+use serde_json::json;
+
+use super::request_id::RequestId;
+
+/// An error that a handler responds with.
+#[derive(Clone, Debug)]
+pub(crate) struct Error {
+    status: StatusCode,
+    detail: Option<String>,
+}
+
+impl Error {
+    pub(crate) fn new(status: StatusCode) -> Self {
+        Self {
+            status,
+            detail: None,
+        }
+    }
+
+    /// The requested resource doesn't exist.
+    pub(crate) fn not_found() -> Self {
+        Self::new(StatusCode::NOT_FOUND)
+    }
+
+    /// Adds an explanation of the error that is shown to the visitor.
+    pub(crate) fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        // The body is rendered by the site's `ErrorPages`, which needs the request.
+        let mut res = self.status.into_response();
+        res.extensions_mut().insert(self);
+        res
+    }
+}
+
+/// An error being rendered for a visitor.
+pub(crate) struct Problem {
+    error: Error,
+    pub(crate) request_id: Option<RequestId>,
+}
+
+impl Problem {
+    pub(crate) fn status(&self) -> u16 {
+        self.error.status.as_u16()
+    }
+
+    pub(crate) fn title(&self) -> &'static str {
+        self.error.status.canonical_reason().unwrap_or("Error")
+    }
+
+    /// Returns an explanation of the error.
+    pub(crate) fn detail(&self) -> &str {
+        match (&self.error.detail, self.error.status) {
+            (Some(detail), _) => detail,
+            (None, StatusCode::NOT_FOUND) => "There is nothing here.",
+            (None, StatusCode::GONE) => "This page has been retired.",
+            (None, StatusCode::MISDIRECTED_REQUEST) => "This server does not host that site.",
+            (None, StatusCode::TOO_MANY_REQUESTS) => {
+                "You are making requests too quickly. Please slow down."
+            }
+            (None, StatusCode::INTERNAL_SERVER_ERROR) => "Something went wrong on our end.",
+            (None, StatusCode::SERVICE_UNAVAILABLE) => {
+                "This is temporarily unavailable. Please try again shortly."
+            }
+            (None, _) => "Something went wrong with your request.",
+        }
+    }
+
+    fn json(&self) -> Response {
+        let mut res = (
+            self.error.status,
+            Json(json!({
+                "type": "about:blank",
+                "title": self.title(),
+                "status": self.status(),
+                "detail": self.detail(),
+                "request_id": self.request_id.as_ref().map(|id| id.to_string()),
+            })),
+        )
+            .into_response();
+        res.headers_mut().insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/problem+json"),
+        );
+        res
+    }
+}
+
+type RenderFn = dyn Fn(Problem) -> askama::Result<String> + Send + Sync;
+
+/// How a site renders error pages.
+#[derive(Clone)]
+pub(crate) struct ErrorPages(Arc<RenderFn>);
+
+/// The error pages used for hosts that don't have their own (and for unknown hosts).
+pub(crate) static DEFAULT: LazyLock<ErrorPages> =
+    LazyLock::new(|| ErrorPages::new(|error| DefaultErrorPage { error }));
+
+#[derive(Template)]
+#[template(path = "error.html")]
+struct DefaultErrorPage {
+    error: Problem,
+}
+
+impl ErrorPages {
+    /// Renders error pages with the template returned by `page`.
+    pub(crate) fn new<T, F>(page: F) -> Self
+    where
+        T: Template,
+        F: Fn(Problem) -> T + Send + Sync + 'static,
+    {
+        Self(Arc::new(move |error| page(error).render()))
+    }
+
+    /// Renders the errors that `router` responds with.
+    pub(crate) fn apply<S>(&self, router: Router<S>) -> Router<S>
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        router.layer(axum::middleware::from_fn_with_state(self.clone(), render))
+    }
+
+    /// Responds to `req` with `error`, rendered with these pages.
+    ///
+    /// This is for middleware that turns requests away before they reach a router (and
+    /// its error pages).
+    pub(crate) fn reject(&self, req: &Request, error: impl IntoResponse) -> Response {
+        self.render(Requested::new(req), error.into_response())
+    }
+
+    fn render(&self, requested: Requested, res: Response) -> Response {
+        let Requested { api, request_id } = requested;
+        let error = match res.extensions().get::<Error>() {
+            Some(error) => error.clone(),
+            // Leave alone error responses that already explain themselves.
+            None if res.status().is_client_error() || res.status().is_server_error() => {
+                if res.body().size_hint().exact() != Some(0) {
+                    return res;
+                }
+                Error::new(res.status())
+            }
+            None => return res,
+        };
+
+        let problem = Problem { error, request_id };
+        let mut page = if api {
+            problem.json()
+        } else {
+            let status = problem.error.status;
+            match (self.0)(problem) {
+                Ok(page) => (status, Html(page)).into_response(),
+                Err(e) => {
+                    tracing::error!("Failed to render error page: {e}");
+                    return res;
+                }
+            }
+        };
+
+        // Keep any headers (like `Allow` or `Retry-After`) that the error came with.
+        let (mut parts, _) = res.into_parts();
+        parts.headers.remove(CONTENT_LENGTH);
+        parts.headers.remove(CONTENT_TYPE);
+        for (name, value) in parts.headers.iter() {
+            page.headers_mut()
+                .entry(name)
+                .or_insert_with(|| value.clone());
+        }
+        page
+    }
+}
+
+/// What we need to know about a request in order to render an error for it.
+struct Requested {
+    api: bool,
+    request_id: Option<RequestId>,
+}
+
+impl Requested {
+    fn new(req: &Request) -> Self {
+        Self {
+            api: req.uri().path().starts_with("/api/"),
+            request_id: req.extensions().get::<RequestId>().cloned(),
+        }
+    }
+}
+
+async fn render(State(pages): State<ErrorPages>, req: Request, next: Next) -> Response {
+    let requested = Requested::new(&req);
+    let res = next.run(req).await;
+    pages.render(requested, res)
+}
@@ -1,28 +1,40 @@
-use std::{env, fmt, iter};
+use std::fmt;
 
 use graphql_client::{GraphQLQuery, Response};
-use reqwest::header::{AUTHORIZATION, HeaderValue};
+use reqwest::header::{AUTHORIZATION, HeaderValue, USER_AGENT};
 use tracing::debug;
 
-const API_URL: &str = "https://api.github.com/graphql";
+use super::upstream;
+use crate::config::Config;
 
 pub struct Client {
-    inner: reqwest::Client,
+    inner: upstream::Client,
+    api_url: String,
+    user_agent: HeaderValue,
+    bearer_auth: HeaderValue,
 }
 
 impl Client {
-    pub fn new(user_agent: &str) -> Result<Self, Error> {
-        let api_key = env::var("GITHUB_API_KEY").map_err(|_| Error::GitHubApiKeyMissing)?;
-        let mut bearer_auth = HeaderValue::from_str(&format!("Bearer {api_key}"))
+    pub fn new(
+        client: &upstream::Client,
+        user_agent: &'static str,
+        config: &Config,
+    ) -> Result<Self, Error> {
+        let api_key = config
+            .github
+            .api_key
+            .as_ref()
+            .ok_or(Error::GitHubApiKeyMissing)?;
+        let mut bearer_auth = HeaderValue::from_str(&format!("Bearer {}", api_key.expose()))
             .map_err(|_| Error::GitHubApiKeyInvalid)?;
         bearer_auth.set_sensitive(true);
 
-        let inner = reqwest::Client::builder()
-            .user_agent(user_agent)
-            .default_headers(iter::once((AUTHORIZATION, bearer_auth)).collect())
-            .build()?;
-
-        Ok(Self { inner })
+        Ok(Self {
+            inner: client.named("github_graphql"),
+            api_url: config.upstreams.github_graphql.clone(),
+            user_agent: HeaderValue::from_static(user_agent),
+            bearer_auth,
+        })
     }
 
     pub async fn post_graphql<Q: GraphQLQuery>(
@@ -33,7 +45,9 @@ impl Client {
 
         let res = self
             .inner
-            .post(API_URL)
+            .post(&self.api_url)
+            .header(USER_AGENT, self.user_agent.clone())
+            .header(AUTHORIZATION, self.bearer_auth.clone())
             .json(&request_body)
             .send()
             .await?
@@ -51,10 +65,9 @@ pub struct GraphQlResponse<Q: GraphQLQuery> {
 
 impl<Q: GraphQLQuery> GraphQlResponse<Q> {
     pub fn into_data(self) -> Result<Q::ResponseData, Vec<graphql_client::Error>> {
-        Ok(self.inner.data.ok_or_else(|| match self.inner.errors {
-            Some(errors) => errors,
-            _ => vec![],
-        })?)
+        self.inner
+            .data
+            .ok_or_else(|| self.inner.errors.unwrap_or_default())
     }
 }
 
@@ -81,7 +94,7 @@ impl Client {
         match &mut response.inner.data {
             None => return Ok(response),
             Some(data) => {
-                let mut page_info = Q::page_info(&data);
+                let mut page_info = Q::page_info(data);
 
                 while page_info.has_next_page {
                     // Fetch the next page.
@@ -124,10 +137,10 @@ impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Error::GitHubApiKeyInvalid => {
-                write!(f, "GITHUB_API_KEY environment variable is invalid")
+                write!(f, "GitHub API key is invalid")
             }
             Error::GitHubApiKeyMissing => {
-                write!(f, "GITHUB_API_KEY environment variable is missing")
+                write!(f, "GitHub API key is not configured (set GITHUB_API_KEY)")
             }
             Error::Request(e) => write!(f, "Error while processing request: {}", e),
         }
@@ -0,0 +1,100 @@
+//! Health and readiness checks.
+//!
+//! - `/_health` reports that the process is up and serving requests. This is what Fly
+//!   checks before routing traffic to us.
+//! - `/_ready` is purely informational, for debugging: it reports the status of every
+//!   background service, which secrets are configured, and when each cached data source
+//!   last refreshed. A missing secret or a failing service only disables some features
+//!   (see [`super::supervisor`]), so it always responds with `200 OK`.
+//!
+//! Both are answered for every host; see [`super::Multiplexer::reserve`].
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use axum::{Json, routing::MethodRouter, routing::get};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use super::supervisor::{ServiceStatus, Supervisor};
+use crate::config::Config;
+
+/// When each cached data source last refreshed (or failed to).
+///
+/// This is held by the [`Supervisor`], and shared with everything that refreshes data.
+#[derive(Clone, Default)]
+pub(crate) struct DataSources(Arc<Mutex<BTreeMap<&'static str, DataSource>>>);
+
+#[derive(Clone, Debug, Default, Serialize)]
+struct DataSource {
+    last_success: Option<DateTime<Utc>>,
+    last_error: Option<DataSourceError>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct DataSourceError {
+    at: DateTime<Utc>,
+    message: String,
+}
+
+impl DataSources {
+    /// Records the outcome of refreshing a cached data source.
+    pub(crate) fn record<T, E: fmt::Display>(&self, source: &'static str, res: &Result<T, E>) {
+        let mut sources = self.0.lock().expect("not poisoned");
+        let entry = sources.entry(source).or_default();
+        match res {
+            Ok(_) => entry.last_success = Some(Utc::now()),
+            Err(e) => {
+                entry.last_error = Some(DataSourceError {
+                    at: Utc::now(),
+                    message: e.to_string(),
+                })
+            }
+        }
+    }
+
+    fn snapshot(&self) -> BTreeMap<&'static str, DataSource> {
+        self.0.lock().expect("not poisoned").clone()
+    }
+}
+
+#[derive(Serialize)]
+struct Health {
+    status: &'static str,
+}
+
+/// Handles `/_health`.
+pub(crate) fn health<S>() -> MethodRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    get(|| async { Json(Health { status: "ok" }) })
+}
+
+#[derive(Serialize)]
+struct Readiness {
+    services: BTreeMap<&'static str, ServiceStatus>,
+    secrets: BTreeMap<&'static str, bool>,
+    data_sources: BTreeMap<&'static str, DataSource>,
+}
+
+/// Handles `/_ready`.
+pub(crate) fn ready<S>(supervisor: Supervisor, config: Arc<Config>) -> MethodRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    get(|| async move {
+        let services = supervisor.statuses();
+        let secrets = config.secrets().into_iter().collect::<BTreeMap<_, _>>();
+        let data_sources = supervisor.data_sources().snapshot();
+
+        Json(Readiness {
+            services,
+            secrets,
+            data_sources,
+        })
+    })
+}
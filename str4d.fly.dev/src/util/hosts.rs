@@ -0,0 +1,348 @@
+//! Declarative configuration of the hosts served by a [`Multiplexer`].
+//!
+//! The configuration file lists every host we serve, along with its aliases and any
+//! static redirects. Hosts that need Rust code refer to a [`Router`] by name; these are
+//! provided at startup via [`Routers`].
+//!
+//! ```toml
+//! [[host]]
+//! name = "str4d.xyz"
+//! aliases = ["www.str4d.xyz"]
+//! router = "str4d.xyz"
+//!
+//! [host.redirects]
+//! "/blog" = { kind = "moved-permanently", to = "https://words.str4d.xyz" }
+//! "/old-project" = { kind = "gone" }
+//!
+//! [host.csp]
+//! style-src = ["'unsafe-inline'"]
+//! img-src = ["https://cdn.bsky.app"]
+//!
+//! [host.rate_limits]
+//! "/api/data/{acronym}" = { per_minute = 10, burst = 5 }
+//! ```
+//!
+//! Every host also serves our [static assets](super::assets) from `/static/`.
+//!
+//! Host names may be wildcards of the form `*.example.com`, which match every subdomain
+//! of `example.com` that isn't configured more specifically. Wildcard hosts can't have
+//! aliases.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use axum::{Router, http::HeaderValue, middleware, routing::MethodRouter};
+use serde::Deserialize;
+
+use super::{
+    Multiplexer, RedirectKind, assets, expose_matched_path, get_gone, get_redir, host_redirect,
+    limits::{RateLimit, RateLimits},
+    normalize_host,
+    security::{ContentSecurityPolicy, SecurityHeaders},
+};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct HostsConfig {
+    #[serde(default, rename = "host")]
+    hosts: Vec<HostConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HostConfig {
+    /// The canonical name of the host.
+    name: String,
+    /// Hosts that redirect to this one.
+    #[serde(default)]
+    aliases: Vec<String>,
+    /// The kind of redirect used for `aliases`.
+    #[serde(default)]
+    alias_redirect: RedirectKind,
+    /// The name of the [`Router`] that handles requests for this host.
+    router: Option<String>,
+    /// Static redirects from a path on this host.
+    ///
+    /// These take precedence over any routes in `router`.
+    #[serde(default)]
+    redirects: BTreeMap<String, PathRule>,
+    /// The sources that pages on this host may load content from.
+    ///
+    /// Aliases only redirect, so they always get the default (deny-all) policy.
+    #[serde(default)]
+    csp: ContentSecurityPolicy,
+    /// How often each client may request the routes of `router`, keyed by route
+    /// template (as declared by the router).
+    #[serde(default)]
+    rate_limits: BTreeMap<String, RateLimit>,
+}
+
+/// What to respond with for a path in a host's redirect table.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case", deny_unknown_fields)]
+enum PathRule {
+    Temporary {
+        to: String,
+    },
+    Permanent {
+        to: String,
+    },
+    MovedPermanently {
+        to: String,
+    },
+    /// The path has been retired, and there is nowhere to redirect it to.
+    Gone {},
+}
+
+impl PathRule {
+    fn target(&self) -> Option<&str> {
+        match self {
+            PathRule::Temporary { to }
+            | PathRule::Permanent { to }
+            | PathRule::MovedPermanently { to } => Some(to),
+            PathRule::Gone {} => None,
+        }
+    }
+
+    fn method_router(&self) -> MethodRouter {
+        match self {
+            PathRule::Temporary { to } => get_redir(RedirectKind::Temporary, to),
+            PathRule::Permanent { to } => get_redir(RedirectKind::Permanent, to),
+            PathRule::MovedPermanently { to } => get_redir(RedirectKind::MovedPermanently, to),
+            PathRule::Gone {} => get_gone(),
+        }
+    }
+}
+
+impl HostsConfig {
+    /// Loads the hosts configuration from the given TOML file.
+    pub(crate) fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path).map_err(|e| Error::Io(path.into(), e))?;
+        toml::from_str(&contents).map_err(|e| Error::Parse(path.into(), e))
+    }
+
+    /// Checks that the configuration is internally consistent, and that every router it
+    /// refers to exists.
+    fn validate<S>(&self, routers: &Routers<S>) -> Result<(), Error> {
+        let mut seen = HashSet::new();
+
+        for host in &self.hosts {
+            for name in Some(&host.name).into_iter().chain(&host.aliases) {
+                let normalized = normalize_host(name);
+                let domain = normalized.strip_prefix("*.").unwrap_or(&normalized);
+                if domain.is_empty() || domain.contains('*') {
+                    return Err(Error::InvalidHostName(name.clone()));
+                }
+                if !seen.insert(normalized) {
+                    return Err(Error::DuplicateHost(name.clone()));
+                }
+            }
+
+            // An alias redirects to the host's name, which must therefore be a real host.
+            if host.name.starts_with("*.") && !host.aliases.is_empty() {
+                return Err(Error::WildcardWithAliases(host.name.clone()));
+            }
+
+            match &host.router {
+                Some(router) if !routers.inner.contains_key(router.as_str()) => {
+                    return Err(Error::UnknownRouter {
+                        host: host.name.clone(),
+                        router: router.clone(),
+                    });
+                }
+                None if host.redirects.is_empty() => {
+                    return Err(Error::NothingToServe(host.name.clone()));
+                }
+                _ => (),
+            }
+
+            if let Some(source) = host.csp.invalid_source() {
+                return Err(Error::InvalidCspSource {
+                    host: host.name.clone(),
+                    source: source.into(),
+                });
+            }
+
+            if !host.rate_limits.is_empty() && host.router.is_none() {
+                return Err(Error::RateLimitsWithoutRouter(host.name.clone()));
+            }
+            for (route, limit) in &host.rate_limits {
+                if !route.starts_with('/') || !limit.is_valid() {
+                    return Err(Error::InvalidRateLimit {
+                        host: host.name.clone(),
+                        route: route.clone(),
+                    });
+                }
+            }
+
+            for (path, rule) in &host.redirects {
+                if !path.starts_with('/') || path.contains(['{', '}']) {
+                    return Err(Error::InvalidRedirectPath {
+                        host: host.name.clone(),
+                        path: path.clone(),
+                    });
+                }
+                if let Some(to) = rule.target()
+                    && HeaderValue::try_from(to).is_err()
+                {
+                    return Err(Error::InvalidRedirectTarget {
+                        host: host.name.clone(),
+                        path: path.clone(),
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The named [`Router`]s that a [`HostsConfig`] can refer to.
+pub(crate) struct Routers<S> {
+    inner: HashMap<&'static str, Router<S>>,
+}
+
+impl<S> Routers<S> {
+    pub(crate) fn new() -> Self {
+        Self {
+            inner: HashMap::new(),
+        }
+    }
+
+    /// Registers a router under the given name.
+    pub(crate) fn register(mut self, name: &'static str, router: Router<S>) -> Self {
+        self.inner.insert(name, router);
+        self
+    }
+}
+
+impl Multiplexer<()> {
+    /// Builds a `Multiplexer` serving the hosts in the given configuration.
+    ///
+    /// Returns an error if the configuration is invalid, or refers to a router that is
+    /// not present in `routers`.
+    pub(crate) fn from_config(config: HostsConfig, routers: Routers<()>) -> Result<Self, Error> {
+        config.validate(&routers)?;
+
+        let mut multiplexer = Multiplexer::new();
+        let mut unused = routers.inner.keys().copied().collect::<HashSet<_>>();
+        let alias_headers = SecurityHeaders::new(&ContentSecurityPolicy::default());
+
+        for host in config.hosts {
+            // Limits are applied before any redirects are added, so that they can see
+            // which of the router's routes each request matched.
+            let router = host.router.map(|name| {
+                unused.remove(name.as_str());
+                let router = routers
+                    .inner
+                    .get(name.as_str())
+                    .cloned()
+                    .expect("validated");
+                RateLimits::new(&host.rate_limits).apply(router)
+            });
+
+            let router = if host.redirects.is_empty() {
+                router.expect("validated")
+            } else {
+                let redirects = host
+                    .redirects
+                    .iter()
+                    .fold(Router::new(), |redirects, (path, rule)| {
+                        redirects.route(path, rule.method_router())
+                    });
+
+                // Requests for any other paths are handled by the named router, if any.
+                match router {
+                    Some(router) => redirects
+                        .fallback_service(router.layer(middleware::from_fn(expose_matched_path))),
+                    None => super::error::DEFAULT.apply(redirects),
+                }
+            };
+
+            // Merged in this order so that the host's own fallback (and the layers that
+            // apply to it, like error pages) is kept even if it is axum's default.
+            let router = SecurityHeaders::new(&host.csp).apply(assets::router().merge(router));
+            multiplexer = multiplexer.handle(host.name.clone(), router);
+            for alias in host.aliases {
+                let redirect = host_redirect(host.name.clone(), host.alias_redirect);
+                multiplexer = multiplexer.handle(alias, alias_headers.apply(redirect));
+            }
+        }
+
+        // Make it obvious when a site has been accidentally left out of the config.
+        for name in unused {
+            tracing::warn!("Router {name} is not used by any configured host");
+        }
+
+        Ok(multiplexer)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum Error {
+    DuplicateHost(String),
+    InvalidCspSource { host: String, source: String },
+    InvalidHostName(String),
+    InvalidRateLimit { host: String, route: String },
+    InvalidRedirectPath { host: String, path: String },
+    InvalidRedirectTarget { host: String, path: String },
+    Io(PathBuf, io::Error),
+    NothingToServe(String),
+    Parse(PathBuf, toml::de::Error),
+    RateLimitsWithoutRouter(String),
+    UnknownRouter { host: String, router: String },
+    WildcardWithAliases(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::DuplicateHost(host) => {
+                write!(f, "Host {host} is configured more than once")
+            }
+            Error::InvalidCspSource { host, source } => write!(
+                f,
+                "Content security policy source {source:?} for host {host} is not a single source expression",
+            ),
+            Error::InvalidHostName(host) => write!(
+                f,
+                "Host name {host:?} is invalid (wildcards are only allowed as a leading `*.`)",
+            ),
+            Error::InvalidRateLimit { host, route } => write!(
+                f,
+                "Rate limit for route {route:?} on host {host} must be for an absolute path, and allow at least one request",
+            ),
+            Error::InvalidRedirectPath { host, path } => write!(
+                f,
+                "Redirect path {path:?} for host {host} must be an absolute path without parameters",
+            ),
+            Error::InvalidRedirectTarget { host, path } => write!(
+                f,
+                "Redirect target for path {path:?} on host {host} is not a valid URI",
+            ),
+            Error::Io(path, e) => write!(f, "Failed to read {}: {e}", path.display()),
+            Error::NothingToServe(host) => write!(
+                f,
+                "Host {host} has neither a router nor redirects, so it and its aliases point nowhere",
+            ),
+            Error::Parse(path, e) => write!(f, "Failed to parse {}: {e}", path.display()),
+            Error::RateLimitsWithoutRouter(host) => write!(
+                f,
+                "Host {host} has rate limits but no router, so there are no routes to limit",
+            ),
+            Error::UnknownRouter { host, router } => {
+                write!(f, "Host {host} refers to unknown router {router}")
+            }
+            Error::WildcardWithAliases(host) => write!(
+                f,
+                "Wildcard host {host} has aliases, but there is no single host to redirect them to",
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
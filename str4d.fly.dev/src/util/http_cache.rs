@@ -0,0 +1,173 @@
+//! HTTP caching of our responses by browsers and proxies.
+//!
+//! Most of our responses are built from data that we cache server-side for a while, so
+//! clients can cache them for as long. Each route declares a [`CachePolicy`] matching the
+//! TTL of the data behind it; responses on that route get a `Cache-Control` header and
+//! an `ETag` (unless the handler sets its own, as [`super::cache::AgedJson`] does), and
+//! conditional requests (`If-None-Match`, or `If-Modified-Since` if the
+//! handler sets `Last-Modified`) for an unchanged response are answered with
+//! `304 Not Modified`.
+
+use std::{
+    convert::Infallible,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, SystemTime},
+};
+
+use axum::{
+    body::Body,
+    extract::Request,
+    response::{IntoResponse, Response},
+};
+use hyper::{
+    Method, StatusCode,
+    header::{
+        CACHE_CONTROL, CONTENT_LENGTH, CONTENT_TYPE, ETAG, HeaderMap, HeaderValue,
+        IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+    },
+};
+use sha2::{Digest, Sha256};
+use tower::{Layer, Service};
+
+/// A [`Layer`] that lets clients cache a route's successful responses.
+///
+/// Responses are buffered in order to compute their `ETag`, so this should only be used
+/// for routes with bounded responses.
+#[derive(Clone)]
+pub(crate) struct CachePolicy {
+    cache_control: HeaderValue,
+}
+
+impl CachePolicy {
+    /// Lets anyone cache responses for up to `max_age`.
+    pub(crate) fn public(max_age: Duration) -> Self {
+        Self {
+            cache_control: HeaderValue::from_str(&format!("public, max-age={}", max_age.as_secs()))
+                .expect("valid"),
+        }
+    }
+
+    /// Lets anyone cache responses forever, for routes whose responses never change
+    /// (like [content-hashed assets](super::assets)).
+    pub(crate) fn immutable() -> Self {
+        Self {
+            cache_control: HeaderValue::from_static("public, max-age=31536000, immutable"),
+        }
+    }
+}
+
+impl<S> Layer<S> for CachePolicy {
+    type Service = CachePolicyService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CachePolicyService {
+            inner,
+            cache_control: self.cache_control.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct CachePolicyService<S> {
+    inner: S,
+    cache_control: HeaderValue,
+}
+
+impl<S> Service<Request> for CachePolicyService<S>
+where
+    S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let cacheable = matches!(*req.method(), Method::GET | Method::HEAD);
+        let conditions = Conditions::new(req.headers());
+        let cache_control = self.cache_control.clone();
+        let mut inner = self.inner.clone();
+
+        Box::pin(async move {
+            let res = inner.call(req).await?;
+            if !cacheable || res.status() != StatusCode::OK {
+                return Ok(res);
+            }
+
+            let (mut parts, body) = res.into_parts();
+            let body = match axum::body::to_bytes(body, usize::MAX).await {
+                Ok(body) => body,
+                Err(e) => {
+                    tracing::error!("Failed to buffer response: {e}");
+                    return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+                }
+            };
+
+            let headers = &mut parts.headers;
+            headers.entry(CACHE_CONTROL).or_insert(cache_control);
+            let etag = headers.entry(ETAG).or_insert_with(|| etag(&body)).clone();
+
+            if conditions.not_modified(&etag, headers.get(LAST_MODIFIED)) {
+                parts.status = StatusCode::NOT_MODIFIED;
+                headers.remove(CONTENT_LENGTH);
+                headers.remove(CONTENT_TYPE);
+                return Ok(Response::from_parts(parts, Body::empty()));
+            }
+
+            Ok(Response::from_parts(parts, Body::from(body)))
+        })
+    }
+}
+
+/// Returns an `ETag` for the given response body.
+///
+/// The tag is weak, because responses may be compressed after it is computed (see
+/// [`super::compression`]), and so aren't byte-for-byte identical across encodings.
+pub(crate) fn etag(body: &[u8]) -> HeaderValue {
+    HeaderValue::from_str(&format!("W/\"{:x}\"", Sha256::digest(body))).expect("valid")
+}
+
+/// Returns the opaque tag of an `ETag`, ignoring whether it is weak.
+fn weak(etag: &[u8]) -> &[u8] {
+    etag.strip_prefix(b"W/").unwrap_or(etag)
+}
+
+/// The conditions of a conditional request.
+struct Conditions {
+    if_none_match: Option<HeaderValue>,
+    if_modified_since: Option<SystemTime>,
+}
+
+impl Conditions {
+    fn new(headers: &HeaderMap) -> Self {
+        Self {
+            if_none_match: headers.get(IF_NONE_MATCH).cloned(),
+            if_modified_since: headers
+                .get(IF_MODIFIED_SINCE)
+                .and_then(|value| httpdate::parse_http_date(value.to_str().ok()?).ok()),
+        }
+    }
+
+    /// Returns whether the client's copy of a response is up to date (RFC 9110 Section
+    /// 13.2.2).
+    fn not_modified(&self, etag: &HeaderValue, last_modified: Option<&HeaderValue>) -> bool {
+        match (&self.if_none_match, self.if_modified_since) {
+            (Some(if_none_match), _) => if_none_match.to_str().is_ok_and(|tags| {
+                tags.split(',').map(str::trim).any(|tag| {
+                    // If-None-Match uses the weak comparison function.
+                    tag == "*" || weak(tag.as_bytes()) == weak(etag.as_bytes())
+                })
+            }),
+            (None, Some(if_modified_since)) => last_modified
+                .and_then(|value| httpdate::parse_http_date(value.to_str().ok()?).ok())
+                .is_some_and(|last_modified| last_modified <= if_modified_since),
+            (None, None) => false,
+        }
+    }
+}
@@ -0,0 +1,226 @@
+//! Limits on how much work clients can make us do.
+//!
+//! Some of our routes are expensive: an uncached `/api/data/{acronym}` on
+//! ietf.rfc.observer makes us crawl datatracker, and clients can pick any acronym. So:
+//!
+//! - Each host can limit how often each client may request each of its routes, with a
+//!   [`RateLimit`] per route in the hosts configuration (see [`super::hosts`]). Every
+//!   client gets a token bucket per route, keyed by their [`ClientIp`]; requests that
+//!   find their bucket empty get `429 Too Many Requests`.
+//! - A [`ConcurrencyLimit`] caps how many requests we handle at once, across every host.
+//!   Rather than queueing requests that we don't have capacity for (so that they time
+//!   out anyway), we shed them with `503 Service Unavailable`.
+//!
+//! Either way, the response has a `Retry-After` header saying when to try again. These
+//! requests are turned away before they reach a site, so they get the [default error
+//! pages](super::error::DEFAULT).
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    Router,
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use hyper::{
+    StatusCode,
+    header::{HeaderValue, RETRY_AFTER},
+};
+use serde::Deserialize;
+use tokio::sync::Semaphore;
+
+use super::{
+    client_ip::ClientIp,
+    error::{self, Error},
+};
+
+/// The name of the counter of requests that we turned away, labelled with the `reason`
+/// (`rate-limited` or `overloaded`).
+pub(crate) const HTTP_REQUESTS_REJECTED: &str = "http.requests.rejected.total";
+
+/// How many clients we track per route before forgetting those whose buckets are full
+/// (which is the same as never having seen them).
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// How often each client may request a route.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RateLimit {
+    /// How many requests a client may make per minute, on average.
+    per_minute: u32,
+    /// How many requests a client may make in quick succession.
+    burst: u32,
+}
+
+impl RateLimit {
+    /// Returns whether this limit lets any requests through.
+    pub(super) fn is_valid(&self) -> bool {
+        self.per_minute > 0 && self.burst > 0
+    }
+
+    /// Returns how many tokens are added to a bucket per second.
+    fn rate(&self) -> f64 {
+        f64::from(self.per_minute) / 60.0
+    }
+}
+
+/// A client's allowance for a route.
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    /// Adds the tokens that have accrued since the bucket was last updated.
+    fn refill(&mut self, limit: &RateLimit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.rate()).min(f64::from(limit.burst));
+        self.updated = now;
+    }
+}
+
+/// The buckets of every client for a route.
+struct Limiter {
+    limit: RateLimit,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl Limiter {
+    fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a token from `client`'s bucket, or returns how long until there is one.
+    fn check(&self, client: IpAddr, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().expect("not poisoned");
+
+        if buckets.len() >= MAX_TRACKED_CLIENTS && !buckets.contains_key(&client) {
+            buckets.retain(|_, bucket| {
+                bucket.refill(&self.limit, now);
+                bucket.tokens < f64::from(self.limit.burst)
+            });
+        }
+
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: f64::from(self.limit.burst),
+            updated: now,
+        });
+        bucket.refill(&self.limit, now);
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.limit.rate(),
+            ))
+        }
+    }
+}
+
+/// The rate limits for the routes of a host.
+pub(crate) struct RateLimits {
+    /// Limiters keyed by route template (e.g. `/api/data/{acronym}`).
+    routes: HashMap<String, Limiter>,
+}
+
+impl RateLimits {
+    pub(crate) fn new(limits: &BTreeMap<String, RateLimit>) -> Self {
+        Self {
+            routes: limits
+                .iter()
+                .map(|(route, limit)| (route.clone(), Limiter::new(*limit)))
+                .collect(),
+        }
+    }
+
+    /// Limits requests to the routes of `router`.
+    ///
+    /// Requests without a [`ClientIp`] (like those in tests) are not limited, as we can't
+    /// tell who sent them.
+    pub(crate) fn apply<S>(self, router: Router<S>) -> Router<S>
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        if self.routes.is_empty() {
+            return router;
+        }
+        router.layer(axum::middleware::from_fn_with_state(
+            Arc::new(self),
+            rate_limit,
+        ))
+    }
+}
+
+async fn rate_limit(State(limits): State<Arc<RateLimits>>, req: Request, next: Next) -> Response {
+    let limiter = req
+        .extensions()
+        .get::<MatchedPath>()
+        .and_then(|route| limits.routes.get(route.as_str()));
+    let client = req.extensions().get::<ClientIp>();
+
+    if let (Some(limiter), Some(&ClientIp(client))) = (limiter, client)
+        && let Err(wait) = limiter.check(client, Instant::now())
+    {
+        metrics::counter!(HTTP_REQUESTS_REJECTED, "reason" => "rate-limited").increment(1);
+        return error::DEFAULT.reject(
+            &req,
+            (
+                [(RETRY_AFTER, retry_after(wait))],
+                Error::new(StatusCode::TOO_MANY_REQUESTS),
+            ),
+        );
+    }
+
+    next.run(req).await
+}
+
+/// A limit on how many requests we handle at once.
+#[derive(Clone)]
+pub(crate) struct ConcurrencyLimit(Arc<Semaphore>);
+
+impl ConcurrencyLimit {
+    /// How long clients are asked to wait before retrying a shed request.
+    const RETRY_AFTER: Duration = Duration::from_secs(1);
+
+    pub(crate) fn new(max: usize) -> Self {
+        Self(Arc::new(Semaphore::new(max)))
+    }
+}
+
+/// Sheds requests that arrive while we are handling as many as the limit allows.
+///
+/// This should be applied with [`super::Multiplexer::layer`].
+pub(crate) async fn shed(
+    State(limit): State<ConcurrencyLimit>,
+    req: Request,
+    next: Next,
+) -> Response {
+    // Held until the response is ready (but not while its body is streamed).
+    let Ok(_permit) = limit.0.try_acquire() else {
+        metrics::counter!(HTTP_REQUESTS_REJECTED, "reason" => "overloaded").increment(1);
+        return error::DEFAULT.reject(
+            &req,
+            (
+                [(RETRY_AFTER, retry_after(ConcurrencyLimit::RETRY_AFTER))],
+                Error::new(StatusCode::SERVICE_UNAVAILABLE),
+            ),
+        );
+    };
+
+    next.run(req).await
+}
+
+/// Returns the `Retry-After` header value for waiting at least `wait`.
+fn retry_after(wait: Duration) -> HeaderValue {
+    let secs = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+    HeaderValue::from(secs.max(1))
+}
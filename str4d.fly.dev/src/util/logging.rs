@@ -0,0 +1,161 @@
+//! Logging and tracing of what we are doing.
+//!
+//! Logs are written to stdout, either as human-readable text or (for log collectors)
+//! as one JSON object per line. Each request we handle gets a tracing span with its
+//! [`RequestId`] and [`ClientIp`], so that every log line emitted while handling it can
+//! be attributed to it.
+//!
+//! Spans can also be exported as traces to an OpenTelemetry collector over OTLP/HTTP,
+//! if `tracing.otlp_endpoint` is configured (see [`crate::config::Tracing`]). Besides
+//! the span for each request (named after its method and route, and labelled with the
+//! host that it was dispatched to), traces include debug-level spans for:
+//! - lookups in our caches, and whether they were hits (see [`super::cache`]);
+//! - every request we make to an upstream (see [`super::upstream`]).
+//!
+//! To look at traces locally, run a collector like Jaeger
+//! (`docker run -p 4318:4318 -p 16686:16686 jaegertracing/jaeger`) and set
+//! `OTLP_ENDPOINT=http://localhost:4318`.
+
+use std::time::Duration;
+
+use axum::extract::{MatchedPath, Request};
+use hyper::Response;
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{Resource, trace::SdkTracerProvider};
+use tower_http::trace::{DefaultOnResponse, OnResponse};
+use tracing::{Span, Subscriber, field::Empty};
+use tracing_subscriber::{
+    EnvFilter, Layer, fmt::format::FmtSpan, layer::SubscriberExt, registry::LookupSpan,
+    util::SubscriberInitExt,
+};
+
+use super::{MatchedHost, client_ip::ClientIp, request_id::RequestId};
+use crate::config::{LogFormat, Tracing};
+
+/// The name that our traces are exported under.
+const SERVICE_NAME: &str = "str4d.fly.dev";
+
+/// Exports traces until it is dropped, at which point any traces that haven't been
+/// exported yet are flushed.
+pub(crate) struct Traces(Option<SdkTracerProvider>);
+
+impl Drop for Traces {
+    fn drop(&mut self) {
+        if let Some(provider) = self.0.take()
+            && let Err(e) = provider.shutdown()
+        {
+            tracing::warn!("Failed to flush traces: {e}");
+        }
+    }
+}
+
+/// Starts writing logs in the given format, and exporting traces if configured to.
+///
+/// Logs are filtered by the `RUST_LOG` env var, or, if it's not set, we default to
+/// showing info-level details. Exported spans are filtered separately, by
+/// `tracing.filter`.
+pub(crate) fn init(format: LogFormat, config: &Tracing) -> Traces {
+    let filter = std::env::var("RUST_LOG").unwrap_or_else(|_| "info".to_owned());
+
+    let fmt = tracing_subscriber::fmt::layer()
+        // Record an event when each span closes. This can be used to time our
+        // routes' durations!
+        .with_span_events(FmtSpan::CLOSE);
+    let fmt = match format {
+        LogFormat::Text => fmt.boxed(),
+        LogFormat::Json => fmt.json().boxed(),
+    };
+
+    let (provider, error) = match config.otlp_endpoint.as_deref().map(exporter) {
+        Some(Ok(exporter)) => (Some(provider(exporter)), None),
+        Some(Err(e)) => (None, Some(e)),
+        None => (None, None),
+    };
+
+    tracing_subscriber::registry()
+        .with(fmt.with_filter(EnvFilter::new(filter)))
+        .with(
+            provider
+                .as_ref()
+                .map(|provider| otel_layer(provider).with_filter(EnvFilter::new(&config.filter))),
+        )
+        .init();
+
+    match (&config.otlp_endpoint, error) {
+        (Some(endpoint), None) => tracing::info!("Exporting traces to {endpoint}"),
+        (Some(endpoint), Some(e)) => {
+            tracing::error!("Failed to start exporting traces to {endpoint}: {e}")
+        }
+        (None, _) => (),
+    }
+
+    Traces(provider)
+}
+
+/// Returns an exporter that sends traces to the OTLP/HTTP collector at `endpoint`.
+fn exporter(endpoint: &str) -> Result<SpanExporter, opentelemetry_otlp::ExporterBuildError> {
+    SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+        .build()
+}
+
+/// Returns a provider that exports traces in batches with the given exporter.
+fn provider(exporter: SpanExporter) -> SdkTracerProvider {
+    SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(SERVICE_NAME).build())
+        .build()
+}
+
+/// Returns a layer that turns spans into traces for the given provider.
+pub(crate) fn otel_layer<S>(provider: &SdkTracerProvider) -> impl Layer<S> + use<S>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer().with_tracer(provider.tracer(SERVICE_NAME))
+}
+
+/// Creates the span for handling a request.
+///
+/// This is called after the request has been dispatched to a host's router and matched
+/// to one of its routes, so the span is named after the route that handles it.
+pub(crate) fn make_span(req: &Request) -> Span {
+    let request_id = req.extensions().get::<RequestId>();
+    let client_ip = req.extensions().get::<ClientIp>();
+    let host = req.extensions().get::<MatchedHost>();
+    let route = req.extensions().get::<MatchedPath>();
+
+    let name = match route {
+        Some(route) => format!("{} {}", req.method(), route.as_str()),
+        None => req.method().to_string(),
+    };
+
+    tracing::info_span!(
+        "request",
+        method = %req.method(),
+        uri = %req.uri(),
+        version = ?req.version(),
+        request_id = request_id.map(tracing::field::display),
+        client_ip = client_ip.map(tracing::field::display),
+        host = host.map(|host| host.0.as_str()),
+        http.route = route.map(|route| route.as_str()),
+        http.response.status_code = Empty,
+        otel.name = name,
+        otel.kind = "server",
+        otel.status_code = Empty,
+    )
+}
+
+/// Records the status of the response to a request in its span.
+pub(crate) fn on_response<B>(res: &Response<B>, latency: Duration, span: &Span) {
+    span.record(
+        "http.response.status_code",
+        i64::from(res.status().as_u16()),
+    );
+    if res.status().is_server_error() {
+        span.record("otel.status_code", "ERROR");
+    }
+    DefaultOnResponse::default().on_response(res, latency, span);
+}
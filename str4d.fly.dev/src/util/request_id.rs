@@ -0,0 +1,41 @@
+//! Identifiers for the requests we handle.
+//!
+//! Every request is assigned a [`RequestId`], which is recorded on the request's tracing
+//! span (see [`super::logging`]), returned to the client in the `X-Request-Id` header,
+//! and shown on error pages. This lets a visitor's report be matched up with our logs.
+
+use std::fmt;
+
+use axum::{extract::Request, middleware::Next, response::Response};
+use hyper::header::{HeaderName, HeaderValue};
+
+pub(crate) const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// The identifier assigned to a request.
+#[derive(Clone, Debug)]
+pub(crate) struct RequestId(String);
+
+impl RequestId {
+    fn new() -> Self {
+        Self(format!("{:032x}", rand::random::<u128>()))
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Assigns a [`RequestId`] to every request.
+pub(crate) async fn assign(mut req: Request, next: Next) -> Response {
+    let request_id = RequestId::new();
+    req.extensions_mut().insert(request_id.clone());
+
+    let mut res = next.run(req).await;
+    res.headers_mut().insert(
+        X_REQUEST_ID,
+        HeaderValue::try_from(request_id.0).expect("hex is valid"),
+    );
+    res
+}
@@ -0,0 +1,148 @@
+//! Jobs that run periodically in the background.
+//!
+//! We use these to keep our caches warm: each [`Job`] refreshes a data set on a fixed
+//! cadence, so that visitors are served data from the cache instead of waiting for it
+//! to be fetched. Jobs run as supervised services (see [`Supervisor`]), so their status
+//! shows up in `/_ready`.
+
+use std::{future::Future, pin::Pin, sync::Arc, time::Duration};
+
+use rand::Rng;
+use tokio::{sync::Semaphore, task::JoinSet};
+
+use super::supervisor::Supervisor;
+
+type RunFn =
+    dyn Fn(Budget) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>> + Send + Sync;
+
+/// A job that runs every `interval`, give or take some jitter.
+#[derive(Clone)]
+pub(crate) struct Job {
+    name: &'static str,
+    interval: Duration,
+    jitter: Duration,
+    concurrency: usize,
+    run: Arc<RunFn>,
+}
+
+impl Job {
+    /// Creates a job that runs `f` every `interval`.
+    ///
+    /// By default a job has no jitter, and a concurrency budget of 1.
+    pub(crate) fn new<F, Fut>(name: &'static str, interval: Duration, f: F) -> Self
+    where
+        F: Fn(Budget) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        Self {
+            name,
+            interval,
+            jitter: Duration::ZERO,
+            concurrency: 1,
+            run: Arc::new(move |budget| Box::pin(f(budget))),
+        }
+    }
+
+    /// Delays each run by a random duration of up to `jitter`, so that jobs with the
+    /// same interval don't all hit their upstreams at once.
+    pub(crate) fn jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Sets how many tasks each run of the job may run at once (see
+    /// [`Budget::run_all`]).
+    pub(crate) fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Runs the job once.
+    pub(crate) async fn run_once(&self) -> anyhow::Result<()> {
+        (self.run)(Budget(Arc::new(Semaphore::new(self.concurrency)))).await
+    }
+
+    fn delay(&self) -> Duration {
+        if self.jitter.is_zero() {
+            Duration::ZERO
+        } else {
+            rand::rng().random_range(Duration::ZERO..self.jitter)
+        }
+    }
+
+    /// Runs the job on its schedule under `supervisor`, starting right away (after
+    /// jitter).
+    pub(crate) fn schedule(self, supervisor: &Supervisor) {
+        let job = Arc::new(self);
+        supervisor.supervise(job.name, move |service| {
+            let job = job.clone();
+            async move {
+                let mut delay = job.delay();
+                loop {
+                    tokio::select! {
+                        _ = tokio::time::sleep(delay) => (),
+                        _ = service.cancelled() => return Ok(()),
+                    }
+
+                    let res = tokio::select! {
+                        res = job.run_once() => res,
+                        _ = service.cancelled() => return Ok(()),
+                    };
+                    match res {
+                        Ok(()) => service.report_success(),
+                        Err(e) => {
+                            tracing::warn!("Job {} failed: {e:#}", job.name);
+                            service.report_error(&e);
+                        }
+                    }
+
+                    delay = job.interval + job.delay();
+                }
+            }
+        });
+    }
+}
+
+/// The concurrency budget of a single run of a [`Job`].
+#[derive(Clone)]
+pub(crate) struct Budget(Arc<Semaphore>);
+
+impl Budget {
+    /// Runs `tasks` to completion, with at most the job's concurrency running at once.
+    ///
+    /// Every task runs even if some fail; the first failure is returned.
+    pub(crate) async fn run_all<Fut>(
+        &self,
+        tasks: impl IntoIterator<Item = Fut>,
+    ) -> anyhow::Result<()>
+    where
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        let mut set = JoinSet::new();
+        let mut total = 0;
+        for task in tasks {
+            total += 1;
+            let semaphore = self.0.clone();
+            set.spawn(async move {
+                let _permit = semaphore.acquire_owned().await?;
+                task.await
+            });
+        }
+
+        let (mut failed, mut first_error) = (0, None);
+        while let Some(res) = set.join_next().await {
+            let res = res
+                .map_err(|e| anyhow::anyhow!("panicked: {e}"))
+                .and_then(|res| res);
+            if let Err(e) = res {
+                failed += 1;
+                first_error.get_or_insert(e);
+            }
+        }
+
+        match first_error {
+            Some(e) => Err(e.context(format!("{failed} of {total} tasks failed"))),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//! Security headers for our responses.
+//!
+//! Every response from a configured host (including alias redirects) tells browsers to
+//! only use HTTPS, to not sniff content types, and to not leak full URLs to other sites.
+//! It also has a Content Security Policy that denies everything by default; each host
+//! opts into the script, style, image and connection sources that its pages need via
+//! its [`ContentSecurityPolicy`] in the hosts configuration (see [`super::hosts`]).
+
+use std::sync::Arc;
+
+use axum::{Router, extract::State, response::Response};
+use hyper::header::{
+    CONTENT_SECURITY_POLICY, HeaderMap, HeaderValue, REFERRER_POLICY, STRICT_TRANSPORT_SECURITY,
+    X_CONTENT_TYPE_OPTIONS,
+};
+use serde::Deserialize;
+
+/// The directives that every policy starts from.
+///
+/// Our pages have no forms or frames, and don't need a `<base>`.
+const BASE_DIRECTIVES: &str =
+    "default-src 'none'; base-uri 'none'; form-action 'none'; frame-ancestors 'none'";
+
+/// The sources that a host's pages may load content from.
+///
+/// Each field is a list of CSP source expressions, like `'self'`, `'unsafe-inline'` or
+/// `https://cdn.example.com`. Kinds of content without any sources are blocked.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct ContentSecurityPolicy {
+    script_src: Vec<String>,
+    style_src: Vec<String>,
+    img_src: Vec<String>,
+    connect_src: Vec<String>,
+}
+
+impl ContentSecurityPolicy {
+    fn directives(&self) -> impl Iterator<Item = (&'static str, &[String])> {
+        [
+            ("script-src", self.script_src.as_slice()),
+            ("style-src", &self.style_src),
+            ("img-src", &self.img_src),
+            ("connect-src", &self.connect_src),
+        ]
+        .into_iter()
+        .filter(|(_, sources)| !sources.is_empty())
+    }
+
+    /// Returns the first source that isn't a single CSP source expression, if any.
+    pub(super) fn invalid_source(&self) -> Option<&str> {
+        self.directives()
+            .flat_map(|(_, sources)| sources)
+            .map(String::as_str)
+            .find(|source| {
+                source.is_empty()
+                    || !source
+                        .bytes()
+                        .all(|b| b.is_ascii_graphic() && b != b';' && b != b',')
+            })
+    }
+
+    /// Returns the `Content-Security-Policy` header value for this policy.
+    fn header_value(&self) -> HeaderValue {
+        let policy =
+            self.directives()
+                .fold(BASE_DIRECTIVES.to_owned(), |mut policy, (name, sources)| {
+                    policy.push_str("; ");
+                    policy.push_str(name);
+                    for source in sources {
+                        policy.push(' ');
+                        policy.push_str(source);
+                    }
+                    policy
+                });
+        HeaderValue::try_from(policy).expect("validated")
+    }
+}
+
+/// The security headers sent with every response from a host.
+#[derive(Clone)]
+pub(crate) struct SecurityHeaders(Arc<HeaderMap>);
+
+impl SecurityHeaders {
+    /// Returns the security headers for a host with the given content security policy.
+    ///
+    /// `csp` must not have an [invalid source](ContentSecurityPolicy::invalid_source).
+    pub(crate) fn new(csp: &ContentSecurityPolicy) -> Self {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            STRICT_TRANSPORT_SECURITY,
+            HeaderValue::from_static("max-age=31536000"),
+        );
+        headers.insert(X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+        headers.insert(
+            REFERRER_POLICY,
+            HeaderValue::from_static("strict-origin-when-cross-origin"),
+        );
+        headers.insert(CONTENT_SECURITY_POLICY, csp.header_value());
+        Self(Arc::new(headers))
+    }
+
+    /// Adds these headers to every response from `router`.
+    ///
+    /// Headers that a handler sets itself are left alone.
+    pub(crate) fn apply<S>(&self, router: Router<S>) -> Router<S>
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        router.layer(axum::middleware::map_response_with_state(
+            self.clone(),
+            add_headers,
+        ))
+    }
+}
+
+async fn add_headers(State(security): State<SecurityHeaders>, mut res: Response) -> Response {
+    let headers = res.headers_mut();
+    for (name, value) in security.0.iter() {
+        headers.entry(name).or_insert_with(|| value.clone());
+    }
+    res
+}
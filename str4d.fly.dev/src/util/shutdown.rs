@@ -0,0 +1,212 @@
+//! Coordinates graceful shutdown of the server and its background tasks.
+
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    pin::Pin,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use axum::extract::Request;
+use tokio::signal;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use tower::{Layer, Service};
+
+/// Tracks the tasks that need to finish before the process can exit, and tells them
+/// when to stop.
+#[derive(Clone)]
+pub(crate) struct Shutdown {
+    token: CancellationToken,
+    tracker: TaskTracker,
+    /// The names of the tasks that are still running, keyed by a unique ID.
+    running: Arc<Mutex<BTreeMap<u64, &'static str>>>,
+    next_id: Arc<AtomicU64>,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl Shutdown {
+    pub(crate) fn new() -> Self {
+        Self {
+            token: CancellationToken::new(),
+            tracker: TaskTracker::new(),
+            running: Arc::new(Mutex::new(BTreeMap::new())),
+            next_id: Arc::new(AtomicU64::new(0)),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Spawns a named task that shutdown will wait for.
+    ///
+    /// The task is given a token that is cancelled when shutdown starts; it should stop
+    /// what it is doing and return promptly once that happens. The task may also cancel
+    /// the token itself to shut down the whole process (e.g. if it fails irrecoverably).
+    pub(crate) fn spawn<F, Fut>(&self, name: &'static str, f: F)
+    where
+        F: FnOnce(CancellationToken) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.running.lock().expect("not poisoned").insert(id, name);
+
+        let running = Running {
+            tasks: self.running.clone(),
+            id,
+        };
+        let task = f(self.token.clone());
+        self.tracker.spawn(async move {
+            let _running = running;
+            task.await;
+            tracing::debug!("{name} stopped");
+        });
+    }
+
+    /// Starts shutting down when the process receives SIGINT or SIGTERM.
+    pub(crate) fn listen_for_signals(&self) {
+        let token = self.token.clone();
+        tokio::spawn(async move {
+            let ctrl_c = async {
+                if let Err(e) = signal::ctrl_c().await {
+                    tracing::error!("Failed to listen for SIGINT: {e}");
+                    std::future::pending::<()>().await;
+                }
+            };
+
+            #[cfg(unix)]
+            let terminate = async {
+                match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+                    Ok(mut sig) => {
+                        sig.recv().await;
+                    }
+                    Err(e) => {
+                        tracing::error!("Failed to listen for SIGTERM: {e}");
+                        std::future::pending::<()>().await;
+                    }
+                }
+            };
+            #[cfg(not(unix))]
+            let terminate = std::future::pending::<()>();
+
+            tokio::select! {
+                _ = ctrl_c => tracing::info!("Received SIGINT"),
+                _ = terminate => tracing::info!("Received SIGTERM"),
+                _ = token.cancelled() => return,
+            }
+            token.cancel();
+        });
+    }
+
+    /// Waits until shutdown has started.
+    pub(crate) async fn started(&self) {
+        self.token.cancelled().await
+    }
+
+    /// Returns a layer that tracks requests that are still being handled.
+    pub(crate) fn layer(&self) -> InFlightLayer {
+        InFlightLayer {
+            in_flight: self.in_flight.clone(),
+        }
+    }
+
+    /// Shuts down, waiting up to `deadline` for spawned tasks to finish.
+    ///
+    /// Logs whatever is still running if the deadline is reached.
+    pub(crate) async fn drain(self, deadline: Duration) {
+        self.token.cancel();
+        self.tracker.close();
+
+        tracing::info!("Shutting down (waiting up to {deadline:?})");
+        if tokio::time::timeout(deadline, self.tracker.wait())
+            .await
+            .is_err()
+        {
+            let running = self.running.lock().expect("not poisoned");
+            tracing::warn!(
+                tasks = ?running.values().collect::<Vec<_>>(),
+                in_flight_requests = self.in_flight.load(Ordering::Relaxed),
+                "Shutdown deadline reached with tasks still running",
+            );
+        } else {
+            tracing::info!("Shutdown complete");
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct InFlightLayer {
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl<S> Layer<S> for InFlightLayer {
+    type Service = InFlightService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        InFlightService {
+            inner,
+            in_flight: self.in_flight.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct InFlightService<S> {
+    inner: S,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl<S> Service<Request> for InFlightService<S>
+where
+    S: Service<Request> + Clone + Send + 'static,
+    S::Future: Send,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = <S::Future as Future>::Output> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let guard = InFlight::new(self.in_flight.clone());
+        let future = self.inner.call(req);
+
+        Box::pin(async move {
+            let res = future.await;
+            drop(guard);
+            res
+        })
+    }
+}
+
+/// Lists a spawned task as running until dropped (even if the task panics).
+struct Running {
+    tasks: Arc<Mutex<BTreeMap<u64, &'static str>>>,
+    id: u64,
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.tasks.lock().expect("not poisoned").remove(&self.id);
+    }
+}
+
+/// Counts a request as in flight until dropped.
+struct InFlight(Arc<AtomicUsize>);
+
+impl InFlight {
+    fn new(counter: Arc<AtomicUsize>) -> Self {
+        counter.fetch_add(1, Ordering::Relaxed);
+        Self(counter)
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,84 @@
+//! Coalescing of concurrent computations of the same value.
+//!
+//! Our caches only help once a value has been computed. When several requests for an
+//! uncached value arrive at once (e.g. right after a deploy), each of them would start
+//! its own (possibly minutes-long) crawl of an upstream. A [`SingleFlight`] instead lets
+//! the first caller for a key do the work, and hands its result to every caller that
+//! arrived in the meantime.
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::OnceCell;
+
+/// Computations of values of type `T`, keyed by `K`, that are in flight.
+pub(crate) struct SingleFlight<K, T> {
+    in_flight: Mutex<HashMap<K, Arc<OnceCell<T>>>>,
+}
+
+impl<K: Clone + Eq + Hash, T: Clone> SingleFlight<K, T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Computes the value for `key` with `f`, unless it is already being computed, in
+    /// which case this waits for that computation and returns its result.
+    ///
+    /// If the caller doing the computation goes away (or `f` panics), one of the callers
+    /// waiting for it takes over with its own `f`.
+    pub(crate) async fn run<F>(&self, key: K, f: impl FnOnce() -> F) -> T
+    where
+        F: Future<Output = T>,
+    {
+        let cell = self
+            .in_flight
+            .lock()
+            .expect("not poisoned")
+            .entry(key.clone())
+            .or_default()
+            .clone();
+        let flight = Flight {
+            in_flight: &self.in_flight,
+            key,
+            cell,
+        };
+
+        flight.cell.get_or_init(f).await.clone()
+    }
+
+    /// Returns the number of keys with computations in flight.
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.in_flight.lock().expect("not poisoned").len()
+    }
+}
+
+/// A caller's interest in an in-flight computation.
+///
+/// When dropped (including when the caller panics or is cancelled), the computation is
+/// forgotten if it has finished, so that later callers compute a new value (the caller is
+/// expected to cache this one if it wants to), or if no other caller is waiting for it.
+struct Flight<'a, K: Eq + Hash, T> {
+    in_flight: &'a Mutex<HashMap<K, Arc<OnceCell<T>>>>,
+    key: K,
+    cell: Arc<OnceCell<T>>,
+}
+
+impl<K: Eq + Hash, T> Drop for Flight<'_, K, T> {
+    fn drop(&mut self) {
+        // Cells are only cloned with the lock held, so if we hold the only reference
+        // besides the map's, nobody else can be waiting for it.
+        let mut in_flight = self.in_flight.lock().expect("not poisoned");
+        if in_flight.get(&self.key).is_some_and(|current| {
+            Arc::ptr_eq(current, &self.cell)
+                && (self.cell.initialized() || Arc::strong_count(&self.cell) == 2)
+        }) {
+            in_flight.remove(&self.key);
+        }
+    }
+}
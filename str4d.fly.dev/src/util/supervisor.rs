@@ -0,0 +1,228 @@
+//! Supervision of long-running background services.
+//!
+//! A service is an async function that runs until shutdown. If it returns early (with
+//! an error, or by panicking), the [`Supervisor`] logs the failure and restarts it after
+//! an exponential backoff. The current status of every service can be queried by name,
+//! so handlers can explain why data they depend on is missing.
+//!
+//! None of our services are needed by every site (the jobs that keep our caches warm can
+//! take a long time to first succeed, and the firehose monitor only powers part of one
+//! site), so a failing service never takes the server out of rotation. Instead, its status
+//! is reported by `/_ready`; see [`super::health`].
+
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio_util::sync::CancellationToken;
+
+use super::{health::DataSources, shutdown::Shutdown};
+
+/// The name of the counter of times that services have been restarted after failing,
+/// labelled with the `service`.
+pub(crate) const SERVICE_RESTARTS: &str = "service.restarts.total";
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+type Statuses = Arc<Mutex<BTreeMap<&'static str, ServiceStatus>>>;
+
+/// Runs background services, restarting them when they fail.
+#[derive(Clone)]
+pub(crate) struct Supervisor {
+    shutdown: Shutdown,
+    statuses: Statuses,
+    data_sources: DataSources,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case", tag = "state")]
+pub(crate) enum ServiceState {
+    /// The service has been (re)started, but hasn't yet reported success.
+    Starting,
+    /// The service has reported success since it was last started.
+    Running,
+    /// The service failed, and will be restarted at the given time.
+    Backoff { retry_at: DateTime<Utc> },
+    /// The service has stopped because we are shutting down.
+    Stopped,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct ServiceStatus {
+    #[serde(flatten)]
+    pub(crate) state: ServiceState,
+    pub(crate) last_success: Option<DateTime<Utc>>,
+    pub(crate) last_error: Option<ServiceError>,
+    pub(crate) restarts: u32,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct ServiceError {
+    pub(crate) at: DateTime<Utc>,
+    pub(crate) message: String,
+}
+
+impl ServiceStatus {
+    fn new() -> Self {
+        Self {
+            state: ServiceState::Starting,
+            last_success: None,
+            last_error: None,
+            restarts: 0,
+        }
+    }
+}
+
+/// Passed to a running service so it can report on its progress.
+#[derive(Clone)]
+pub(crate) struct ServiceHandle {
+    name: &'static str,
+    statuses: Statuses,
+    token: CancellationToken,
+}
+
+impl ServiceHandle {
+    fn update(&self, f: impl FnOnce(&mut ServiceStatus)) {
+        let mut statuses = self.statuses.lock().expect("not poisoned");
+        f(statuses.entry(self.name).or_insert_with(ServiceStatus::new));
+    }
+
+    /// Records that the service has successfully done some work.
+    pub(crate) fn report_success(&self) {
+        self.update(|status| {
+            status.state = ServiceState::Running;
+            status.last_success = Some(Utc::now());
+        });
+    }
+
+    /// Records an error that the service recovered from without restarting.
+    pub(crate) fn report_error(&self, e: &anyhow::Error) {
+        self.update(|status| {
+            status.last_error = Some(ServiceError {
+                at: Utc::now(),
+                message: format!("{e:#}"),
+            });
+        });
+    }
+
+    /// Waits until the service should stop.
+    pub(crate) async fn cancelled(&self) {
+        self.token.cancelled().await
+    }
+}
+
+impl Supervisor {
+    pub(crate) fn new(shutdown: Shutdown) -> Self {
+        Self {
+            shutdown,
+            statuses: Arc::new(Mutex::new(BTreeMap::new())),
+            data_sources: DataSources::default(),
+        }
+    }
+
+    /// Returns the data sources that our services and handlers refresh.
+    pub(crate) fn data_sources(&self) -> &DataSources {
+        &self.data_sources
+    }
+
+    /// Spawns a one-off background task (like a cache refresh) that shutdown cancels, and
+    /// waits for.
+    pub(crate) fn spawn<Fut>(&self, name: &'static str, task: Fut)
+    where
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.shutdown.spawn(name, |token| async move {
+            tokio::select! {
+                _ = task => (),
+                _ = token.cancelled() => (),
+            }
+        });
+    }
+
+    /// Returns the status of the named service, or `None` if it isn't running here.
+    pub(crate) fn status(&self, name: &str) -> Option<ServiceStatus> {
+        self.statuses
+            .lock()
+            .expect("not poisoned")
+            .get(name)
+            .cloned()
+    }
+
+    /// Returns the status of every supervised service.
+    pub(crate) fn statuses(&self) -> BTreeMap<&'static str, ServiceStatus> {
+        self.statuses.lock().expect("not poisoned").clone()
+    }
+
+    /// Starts a named service, restarting it whenever it returns before shutdown.
+    ///
+    /// The service should return once [`ServiceHandle::cancelled`] completes.
+    pub(crate) fn supervise<F, Fut>(&self, name: &'static str, f: F)
+    where
+        F: Fn(ServiceHandle) -> Fut + Send + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        let statuses = self.statuses.clone();
+        statuses
+            .lock()
+            .expect("not poisoned")
+            .insert(name, ServiceStatus::new());
+
+        self.shutdown.spawn(name, move |token| async move {
+            let handle = ServiceHandle {
+                name,
+                statuses,
+                token: token.clone(),
+            };
+            let mut backoff = INITIAL_BACKOFF;
+
+            loop {
+                let started = Utc::now();
+                handle.update(|status| status.state = ServiceState::Starting);
+
+                // Run the service in its own task so that we can recover from panics.
+                let res = match tokio::spawn(f(handle.clone())).await {
+                    Ok(res) => res,
+                    Err(e) => Err(anyhow::anyhow!("panicked: {e}")),
+                };
+                if token.is_cancelled() {
+                    break;
+                }
+                let e = res
+                    .err()
+                    .unwrap_or_else(|| anyhow::anyhow!("exited unexpectedly"));
+
+                let now = Utc::now();
+                handle.update(|status| {
+                    // A service that was working before it failed gets a fresh backoff.
+                    if status.last_success.is_some_and(|t| t >= started) {
+                        backoff = INITIAL_BACKOFF;
+                    }
+                    status.state = ServiceState::Backoff {
+                        retry_at: now + backoff,
+                    };
+                    status.last_error = Some(ServiceError {
+                        at: now,
+                        message: format!("{e:#}"),
+                    });
+                    status.restarts += 1;
+                });
+                tracing::error!("Service {name} failed, restarting in {backoff:?}: {e:?}");
+                metrics::counter!(SERVICE_RESTARTS, "service" => name).increment(1);
+
+                tokio::select! {
+                    _ = tokio::time::sleep(backoff) => (),
+                    _ = token.cancelled() => break,
+                }
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+
+            handle.update(|status| status.state = ServiceState::Stopped);
+        });
+    }
+}
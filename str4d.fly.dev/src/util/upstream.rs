@@ -0,0 +1,713 @@
+//! Outbound HTTP requests to the upstreams we fetch data from.
+//!
+//! Every upstream request goes through a [`Client`]. This lets us measure how each
+//! upstream is behaving: requests are counted and timed per upstream (as named with
+//! [`Client::named`]), along with the bytes we receive and the kinds of errors we see.
+//! Each request also gets a debug-level `upstream` span, so that it shows up in traces
+//! (see [`super::logging`]).
+//!
+//! Every request is made with the same policy (see [`crate::config::Outbound`]), so
+//! that a misbehaving upstream can't hold up our pages or background jobs forever:
+//!
+//! - Connecting to an upstream, and receiving each of its responses, are bounded by
+//!   timeouts.
+//! - `GET` requests that fail, or get a `5xx` response, are retried a few times, after
+//!   an exponentially increasing (and jittered) delay.
+//! - Each host of each upstream has a circuit breaker (some upstreams, like relays, are
+//!   spread over many hosts that fail independently). Once enough requests to a host
+//!   fail in a row (after retries), it opens: we stop sending requests to the host, and
+//!   instead respond to them with `503 Service Unavailable`. After a cooldown, we let
+//!   one request through; if it succeeds, the breaker closes again.
+//!
+//! It also lets us capture real upstream traffic for building test fixtures, and serve
+//! it back later without touching the network (see [`crate::config::Recording`]):
+//!
+//! - In record mode, each request and its response are written to
+//!   `<dir>/<host>/<method>-<hash>.json`.
+//! - In replay mode, responses are served from those files and no requests are sent.
+//!   Requests without a recording get a `502 Bad Gateway` response. As the network
+//!   isn't involved, these aren't retried, and don't trip circuit breakers.
+//!
+//! Requests are matched on their method, URL, and a hash of their body; headers are
+//! ignored, so secrets sent in them don't need to be the real ones when replaying.
+//! Responses can contain secrets though (e.g. Bluesky session tokens), so review
+//! recordings before committing them.
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use atrium_xrpc::http;
+use axum::body::Bytes;
+use hyper::{
+    HeaderMap, Method, StatusCode,
+    header::{CONNECTION, CONTENT_LENGTH, HeaderName, HeaderValue, SET_COOKIE, TRANSFER_ENCODING},
+};
+use rand::Rng;
+use reqwest::IntoUrl;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{Instrument, field::Empty};
+
+use super::{cache, method_label, status_class};
+use crate::config::{Outbound, Recording, RecordingMode};
+
+/// The name of the counter of requests that we have made to upstreams.
+pub(crate) const UPSTREAM_REQUESTS: &str = "upstream.requests.total";
+
+/// The name of the histogram of how long upstreams take to respond, including the time
+/// taken to receive the response body.
+pub(crate) const UPSTREAM_REQUEST_DURATION: &str = "upstream.request.duration.seconds";
+
+/// The buckets (in seconds) for [`UPSTREAM_REQUEST_DURATION`].
+pub(crate) const UPSTREAM_REQUEST_DURATION_BUCKETS: &[f64] =
+    &[0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// The name of the counter of response body bytes that we have received from upstreams.
+pub(crate) const UPSTREAM_RESPONSE_BYTES: &str = "upstream.response.bytes.total";
+
+/// The name of the counter of requests to upstreams that failed without a response.
+pub(crate) const UPSTREAM_ERRORS: &str = "upstream.errors.total";
+
+/// The name of the counter of retried requests to upstreams.
+///
+/// Retries aren't counted as separate requests in [`UPSTREAM_REQUESTS`]; a request is
+/// counted (and timed) once, with the outcome of its last attempt.
+pub(crate) const UPSTREAM_RETRIES: &str = "upstream.retries.total";
+
+/// The name of the gauge of the state of the circuit breaker of each upstream host: 0
+/// when it is closed, 1 when it is letting a request through to check if the host has
+/// recovered, and 2 when it is open.
+pub(crate) const UPSTREAM_CIRCUIT_STATE: &str = "upstream.circuit.state";
+
+/// The name of the counter of requests that we didn't send because the upstream host's
+/// circuit breaker was open.
+pub(crate) const UPSTREAM_CIRCUIT_REJECTED: &str = "upstream.circuit.rejected.total";
+
+/// The user agent that we send to upstreams.
+const USER_AGENT: &str = "atp.fyi";
+
+/// The delay before the first retry of a request. Each further retry waits twice as
+/// long as the one before it.
+const RETRY_BACKOFF: Duration = Duration::from_millis(250);
+
+/// A client for making requests to upstreams.
+///
+/// This is cheap to clone, and clones share recordings and circuit breakers.
+#[derive(Clone)]
+pub(crate) struct Client {
+    inner: reqwest::Client,
+    /// The upstream that requests are labelled with in metrics.
+    name: &'static str,
+    policy: Arc<Outbound>,
+    breakers: Arc<Breakers>,
+    tape: Option<Arc<Tape>>,
+}
+
+impl Client {
+    pub(crate) fn new(recording: &Recording, policy: &Outbound) -> Result<Self, reqwest::Error> {
+        let inner = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .connect_timeout(Duration::from_secs(policy.connect_timeout_secs))
+            .build()?;
+        let tape = recording.mode.map(|mode| {
+            let dir = recording.dir.display();
+            match mode {
+                RecordingMode::Record => tracing::info!("Recording upstream traffic to {dir}"),
+                RecordingMode::Replay => tracing::info!("Replaying upstream traffic from {dir}"),
+            }
+            Arc::new(Tape {
+                mode,
+                dir: recording.dir.clone(),
+            })
+        });
+
+        Ok(Self {
+            inner,
+            name: "other",
+            policy: Arc::new(policy.clone()),
+            breakers: Arc::new(Breakers::new(policy)),
+            tape,
+        })
+    }
+
+    /// Returns a client whose requests are labelled in metrics as being for the given
+    /// upstream.
+    ///
+    /// Names should be one of the fields of [`crate::config::Upstreams`], so that they
+    /// are easy to correlate with configuration.
+    pub(crate) fn named(&self, name: &'static str) -> Self {
+        Self {
+            name,
+            ..self.clone()
+        }
+    }
+
+    pub(crate) fn get(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.request(Method::GET, url)
+    }
+
+    pub(crate) fn post(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.request(Method::POST, url)
+    }
+
+    pub(crate) fn request(&self, method: Method, url: impl IntoUrl) -> RequestBuilder {
+        RequestBuilder {
+            client: self.clone(),
+            inner: self.inner.request(method, url),
+        }
+    }
+
+    /// Returns an XRPC client for the service at `base_uri`, that sends its requests
+    /// through this client.
+    pub(crate) fn xrpc(&self, base_uri: impl Into<String>) -> XrpcClient {
+        XrpcClient {
+            client: self.clone(),
+            base_uri: base_uri.into(),
+        }
+    }
+
+    pub(crate) async fn execute(
+        &self,
+        mut req: reqwest::Request,
+    ) -> Result<reqwest::Response, reqwest::Error> {
+        let method = method_label(req.method());
+        let span = tracing::debug_span!(
+            "upstream",
+            upstream = self.name,
+            http.request.method = method,
+            url.full = %req.url(),
+            http.response.status_code = Empty,
+            otel.name = format!("{method} {}", self.name),
+            otel.kind = "client",
+            otel.status_code = Empty,
+        );
+
+        let replaying = self
+            .tape
+            .as_ref()
+            .is_some_and(|tape| tape.mode == RecordingMode::Replay);
+        let endpoint = Endpoint::new(self.name, req.url());
+        if !replaying && !self.breakers.admit(&endpoint) {
+            tracing::debug!("Not sending request to {endpoint}: circuit is open");
+            metrics::counter!(
+                UPSTREAM_CIRCUIT_REJECTED,
+                "upstream" => self.name,
+                "host" => endpoint.host.clone(),
+            )
+            .increment(1);
+            let res = Buffered::empty(StatusCode::SERVICE_UNAVAILABLE);
+            span.record("http.response.status_code", i64::from(res.status.as_u16()));
+            span.record("otel.status_code", "ERROR");
+            return Ok(res.into_response());
+        }
+
+        req.timeout_mut()
+            .get_or_insert(Duration::from_secs(self.policy.timeout_secs));
+        let start = Instant::now();
+        let res = self.send(req, replaying).instrument(span.clone()).await;
+        let elapsed = start.elapsed();
+
+        let status = match &res {
+            Ok(res) => {
+                span.record("http.response.status_code", i64::from(res.status.as_u16()));
+                status_class(res.status)
+            }
+            Err(_) => "error",
+        };
+        let failed = matches!(status, "5xx" | "error");
+        if failed {
+            span.record("otel.status_code", "ERROR");
+        }
+        if !replaying {
+            self.breakers.record(&endpoint, !failed);
+        }
+        let labels = [
+            ("upstream", self.name),
+            ("method", method),
+            ("status", status),
+        ];
+        metrics::counter!(UPSTREAM_REQUESTS, &labels).increment(1);
+        metrics::histogram!(UPSTREAM_REQUEST_DURATION, &labels).record(elapsed.as_secs_f64());
+
+        match res {
+            Ok(res) => {
+                metrics::counter!(UPSTREAM_RESPONSE_BYTES, "upstream" => self.name)
+                    .increment(res.body.len() as u64);
+                Ok(res.into_response())
+            }
+            Err(e) => {
+                tracing::debug!("Request to {} failed: {e}", self.name);
+                metrics::counter!(
+                    UPSTREAM_ERRORS,
+                    "upstream" => self.name,
+                    "kind" => error_kind(&e),
+                )
+                .increment(1);
+                Err(e)
+            }
+        }
+    }
+
+    /// Sends the given request (or replays it), retrying it if it is a `GET` that fails.
+    async fn send(
+        &self,
+        mut req: reqwest::Request,
+        replaying: bool,
+    ) -> Result<Buffered, reqwest::Error> {
+        let retries = match *req.method() {
+            Method::GET if !replaying => self.policy.retries,
+            _ => 0,
+        };
+
+        for attempt in 1..=retries {
+            // Only requests with streamed bodies can't be cloned, and GETs have no body.
+            let Some(retry) = req.try_clone() else { break };
+            let res = self.fetch(req).await;
+            let retryable = match &res {
+                Ok(res) => res.status.is_server_error(),
+                Err(e) => !e.is_builder(),
+            };
+            if !retryable {
+                return res;
+            }
+
+            let delay = backoff(attempt);
+            tracing::debug!("Retrying request to {} in {delay:?}", self.name);
+            metrics::counter!(UPSTREAM_RETRIES, "upstream" => self.name).increment(1);
+            tokio::time::sleep(delay).await;
+            req = retry;
+        }
+
+        self.fetch(req).await
+    }
+
+    /// Sends the given request (or replays it), and reads the entire response.
+    async fn fetch(&self, req: reqwest::Request) -> Result<Buffered, reqwest::Error> {
+        let Some(tape) = self.tape.as_deref() else {
+            return Buffered::read(self.inner.execute(req).await?).await;
+        };
+
+        let key = Key::new(&req);
+        match tape.mode {
+            RecordingMode::Record => {
+                let res = Buffered::read(self.inner.execute(req).await?).await?;
+                tape.record(key, &res).await;
+                Ok(res)
+            }
+            RecordingMode::Replay => Ok(tape.replay(key).await),
+        }
+    }
+}
+
+/// Returns how long to wait before the given retry (counting from 1) of a request.
+///
+/// The delay is jittered, so that requests that failed together aren't retried
+/// together.
+fn backoff(attempt: u32) -> Duration {
+    let max = RETRY_BACKOFF.saturating_mul(2u32.saturating_pow(attempt - 1));
+    max / 2 + rand::rng().random_range(Duration::ZERO..=max / 2)
+}
+
+/// A host of an upstream, which has its own circuit breaker.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Endpoint {
+    upstream: &'static str,
+    /// The host (and port, if it isn't the default for the scheme) of the URL.
+    host: String,
+}
+
+impl Endpoint {
+    fn new(upstream: &'static str, url: &reqwest::Url) -> Self {
+        let host = url.host_str().unwrap_or_default();
+        let host = match url.port() {
+            Some(port) => format!("{host}:{port}"),
+            None => host.to_owned(),
+        };
+        Self { upstream, host }
+    }
+
+    /// Returns the [`UPSTREAM_CIRCUIT_STATE`] gauge for this endpoint.
+    fn circuit_gauge(&self) -> metrics::Gauge {
+        metrics::gauge!(
+            UPSTREAM_CIRCUIT_STATE,
+            "upstream" => self.upstream,
+            "host" => self.host.clone(),
+        )
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.upstream, self.host)
+    }
+}
+
+/// The circuit breakers of every upstream host.
+struct Breakers {
+    /// How many requests in a row must fail to open a circuit.
+    threshold: u32,
+    /// How long a circuit stays open.
+    cooldown: Duration,
+    circuits: Mutex<HashMap<Endpoint, Circuit>>,
+}
+
+/// The state of an upstream host's circuit breaker.
+#[derive(Clone, Copy)]
+enum Circuit {
+    /// Requests are sent. This many of them have failed in a row.
+    Closed { failures: u32 },
+    /// Requests aren't sent until the given time.
+    Open { until: Instant },
+    /// A request has been sent to check whether the upstream has recovered, and other
+    /// requests aren't sent until it finishes (or until the given time, in case it
+    /// never does).
+    HalfOpen { until: Instant },
+}
+
+impl Circuit {
+    /// Returns the value of [`UPSTREAM_CIRCUIT_STATE`] for this state.
+    fn gauge(&self) -> f64 {
+        match self {
+            Circuit::Closed { .. } => 0.0,
+            Circuit::HalfOpen { .. } => 1.0,
+            Circuit::Open { .. } => 2.0,
+        }
+    }
+}
+
+impl Breakers {
+    fn new(policy: &Outbound) -> Self {
+        Self {
+            threshold: policy.breaker_threshold,
+            cooldown: Duration::from_secs(policy.breaker_cooldown_secs),
+            circuits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns whether a request may be sent to `endpoint`.
+    fn admit(&self, endpoint: &Endpoint) -> bool {
+        if self.threshold == 0 {
+            return true;
+        }
+
+        let now = Instant::now();
+        let mut circuits = self.circuits.lock().expect("not poisoned");
+        let circuit = circuits
+            .entry(endpoint.clone())
+            .or_insert(Circuit::Closed { failures: 0 });
+        match *circuit {
+            Circuit::Closed { .. } => true,
+            Circuit::Open { until } | Circuit::HalfOpen { until } if now < until => false,
+            Circuit::Open { .. } | Circuit::HalfOpen { .. } => {
+                *circuit = Circuit::HalfOpen {
+                    until: now + self.cooldown,
+                };
+                endpoint.circuit_gauge().set(circuit.gauge());
+                true
+            }
+        }
+    }
+
+    /// Records the outcome of a request to `endpoint`.
+    fn record(&self, endpoint: &Endpoint, success: bool) {
+        if self.threshold == 0 {
+            return;
+        }
+
+        let mut circuits = self.circuits.lock().expect("not poisoned");
+        let circuit = circuits
+            .entry(endpoint.clone())
+            .or_insert(Circuit::Closed { failures: 0 });
+        let open = Circuit::Open {
+            until: Instant::now() + self.cooldown,
+        };
+        let next = match (*circuit, success) {
+            (Circuit::Closed { .. }, true) => Circuit::Closed { failures: 0 },
+            (_, true) => {
+                tracing::info!("{endpoint} has recovered; closing its circuit");
+                Circuit::Closed { failures: 0 }
+            }
+            (Circuit::Closed { failures }, false) if failures + 1 < self.threshold => {
+                Circuit::Closed {
+                    failures: failures + 1,
+                }
+            }
+            (Circuit::Closed { .. }, false) => {
+                tracing::warn!(
+                    "{} requests in a row to {endpoint} failed; opening its circuit for {:?}",
+                    self.threshold,
+                    self.cooldown,
+                );
+                open
+            }
+            (Circuit::HalfOpen { .. }, false) => {
+                tracing::warn!("{endpoint} is still failing; keeping its circuit open");
+                open
+            }
+            // A request that was sent before the circuit opened.
+            (Circuit::Open { .. }, false) => *circuit,
+        };
+        *circuit = next;
+        endpoint.circuit_gauge().set(next.gauge());
+    }
+}
+
+/// Returns the kind of the given error, for labelling metrics.
+fn error_kind(e: &reqwest::Error) -> &'static str {
+    if e.is_timeout() {
+        "timeout"
+    } else if e.is_connect() {
+        "connect"
+    } else if e.is_redirect() {
+        "redirect"
+    } else if e.is_body() || e.is_decode() {
+        "body"
+    } else if e.is_builder() {
+        "builder"
+    } else {
+        "request"
+    }
+}
+
+/// A response whose body has been read.
+///
+/// Every caller reads the entire response anyway, and reading it here means that
+/// recordings and metrics cover the whole response.
+struct Buffered {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl Buffered {
+    /// Returns a response with the given status, and nothing else.
+    fn empty(status: StatusCode) -> Self {
+        Self {
+            status,
+            headers: HeaderMap::new(),
+            body: Bytes::new(),
+        }
+    }
+
+    async fn read(res: reqwest::Response) -> Result<Self, reqwest::Error> {
+        let status = res.status();
+        let headers = res.headers().clone();
+        let body = res.bytes().await?;
+        Ok(Self {
+            status,
+            headers,
+            body,
+        })
+    }
+
+    fn into_response(self) -> reqwest::Response {
+        let mut res = http::Response::new(self.body);
+        *res.status_mut() = self.status;
+        *res.headers_mut() = self.headers;
+        res.into()
+    }
+}
+
+/// A builder for a request to an upstream.
+pub(crate) struct RequestBuilder {
+    client: Client,
+    inner: reqwest::RequestBuilder,
+}
+
+impl RequestBuilder {
+    pub(crate) fn header(self, key: HeaderName, value: HeaderValue) -> Self {
+        Self {
+            client: self.client,
+            inner: self.inner.header(key, value),
+        }
+    }
+
+    pub(crate) fn json<T: Serialize + ?Sized>(self, json: &T) -> Self {
+        Self {
+            client: self.client,
+            inner: self.inner.json(json),
+        }
+    }
+
+    pub(crate) async fn send(self) -> Result<reqwest::Response, reqwest::Error> {
+        self.client.execute(self.inner.build()?).await
+    }
+}
+
+/// An XRPC client for use with `atrium_api`.
+#[derive(Clone)]
+pub(crate) struct XrpcClient {
+    client: Client,
+    base_uri: String,
+}
+
+impl atrium_xrpc::HttpClient for XrpcClient {
+    async fn send_http(
+        &self,
+        request: http::Request<Vec<u8>>,
+    ) -> Result<http::Response<Vec<u8>>, Box<dyn Error + Send + Sync + 'static>> {
+        let response = self.client.execute(request.try_into()?).await?;
+        let mut builder = http::Response::builder().status(response.status());
+        for (k, v) in response.headers() {
+            builder = builder.header(k, v);
+        }
+        builder
+            .body(response.bytes().await?.to_vec())
+            .map_err(Into::into)
+    }
+}
+
+impl atrium_xrpc::XrpcClient for XrpcClient {
+    fn base_uri(&self) -> String {
+        self.base_uri.clone()
+    }
+}
+
+/// Where recordings are written to or read from.
+struct Tape {
+    mode: RecordingMode,
+    dir: PathBuf,
+}
+
+/// The parts of a request that we match recordings on.
+#[derive(PartialEq, Eq, Deserialize, Serialize)]
+struct Key {
+    method: String,
+    url: String,
+    body_sha256: String,
+}
+
+impl Key {
+    fn new(req: &reqwest::Request) -> Self {
+        let body = req
+            .body()
+            .and_then(|body| body.as_bytes())
+            .unwrap_or_default();
+        Self {
+            method: req.method().to_string(),
+            url: req.url().to_string(),
+            body_sha256: format!("{:x}", Sha256::digest(body)),
+        }
+    }
+}
+
+/// A recorded request and its response.
+#[derive(Deserialize, Serialize)]
+struct Exchange {
+    #[serde(flatten)]
+    key: Key,
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Tape {
+    fn path(&self, key: &Key) -> PathBuf {
+        let (host, port) = reqwest::Url::parse(&key.url)
+            .map(|url| (url.host_str().unwrap_or("unknown").to_owned(), url.port()))
+            .unwrap_or(("unknown".into(), None));
+        let hash = Sha256::new()
+            .chain_update(&key.method)
+            .chain_update(" ")
+            .chain_update(&key.url)
+            .chain_update(" ")
+            .chain_update(&key.body_sha256)
+            .finalize();
+
+        self.dir
+            .join(match port {
+                Some(port) => format!("{host}_{port}"),
+                None => host,
+            })
+            .join(format!("{}-{hash:x}.json", key.method))
+    }
+
+    /// Records the given response.
+    async fn record(&self, key: Key, res: &Buffered) {
+        match str::from_utf8(&res.body) {
+            Ok(text) => {
+                let path = self.path(&key);
+                let exchange = Exchange {
+                    key,
+                    status: res.status.as_u16(),
+                    headers: res
+                        .headers
+                        .iter()
+                        .filter(|(name, _)| !UNRECORDED_HEADERS.contains(name))
+                        .filter_map(|(name, value)| {
+                            Some((name.to_string(), value.to_str().ok()?.to_owned()))
+                        })
+                        .collect(),
+                    body: text.to_owned(),
+                };
+                if let Err(e) = write(&path, &exchange).await {
+                    tracing::warn!("Failed to write recording {}: {e}", path.display());
+                }
+            }
+            Err(_) => tracing::warn!(
+                "Not recording non-UTF-8 response for {} {}",
+                key.method,
+                key.url,
+            ),
+        }
+    }
+
+    /// Returns the recorded response for the given request.
+    async fn replay(&self, key: Key) -> Buffered {
+        let path = self.path(&key);
+        let exchange = match tokio::fs::read_to_string(&path).await {
+            Ok(contents) => serde_json::from_str::<Exchange>(&contents).map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+
+        match exchange {
+            Ok(exchange) if exchange.key == key => Buffered {
+                status: StatusCode::from_u16(exchange.status).unwrap_or(StatusCode::BAD_GATEWAY),
+                headers: exchange
+                    .headers
+                    .iter()
+                    .filter_map(|(name, value)| {
+                        Some((
+                            HeaderName::try_from(name).ok()?,
+                            HeaderValue::try_from(value).ok()?,
+                        ))
+                    })
+                    .collect(),
+                body: exchange.body.into(),
+            },
+            res => {
+                let reason = match res {
+                    Ok(_) => "recording is for a different request".into(),
+                    Err(e) => e,
+                };
+                tracing::warn!(
+                    "No recording for {} {} at {} ({reason})",
+                    key.method,
+                    key.url,
+                    path.display(),
+                );
+                Buffered {
+                    status: StatusCode::BAD_GATEWAY,
+                    headers: HeaderMap::new(),
+                    body: format!("No recording for {} {}", key.method, key.url).into(),
+                }
+            }
+        }
+    }
+}
+
+/// Headers that describe the connection a response was sent over, rather than the
+/// response itself.
+const UNRECORDED_HEADERS: &[HeaderName] =
+    &[CONNECTION, CONTENT_LENGTH, SET_COOKIE, TRANSFER_ENCODING];
+
+/// Writes a recording, pretty-printed so that it can be edited into a fixture.
+async fn write(path: &std::path::Path, exchange: &Exchange) -> Result<(), Box<dyn Error>> {
+    cache::write(path, &serde_json::to_vec_pretty(exchange)?).await
+}